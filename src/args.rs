@@ -1,4 +1,4 @@
-use getargs::{Opt, Options};
+use getargs::{Arg, Opt, Options};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -13,6 +13,14 @@ pub enum UsageError<'a> {
     UnknownOpt(Opt<&'a str>),
     #[error("unknown subcommand {0:?}")]
     UnknownSubcommand(&'a str),
+    #[error("invalid value {1:?} for option {0}")]
+    InvalidValue(Opt<&'a str>, &'a str),
+    #[error("missing required option {0}")]
+    MissingOpt(&'a str),
+    #[error("missing argument {0}")]
+    MissingArg(&'a str),
+    #[error("unexpected argument {0:?}")]
+    UnexpectedArg(&'a str),
 }
 
 impl<'a> From<getargs::Error<&'a str>> for UsageError<'a> {
@@ -41,6 +49,46 @@ pub enum Subcommand<'a> {
     Test {
         program: Option<&'a str>,
         tests: Vec<&'a str>,
+        jobs: Option<usize>,
+        bless: bool,
+        samples: bool,
+        skip: bool,
+        side_by_side: bool,
+    },
+    TestAdd {
+        program: Option<&'a str>,
+        editor: bool,
+        delimiter: &'a str,
+        compress: bool,
+    },
+    Bench {
+        program: Option<&'a str>,
+        tests: Vec<&'a str>,
+        runs: usize,
+        warmup: usize,
+    },
+    Stress {
+        program: Option<&'a str>,
+        generator: &'a str,
+        reference: &'a str,
+        count: Option<u64>,
+        seed: u64,
+    },
+    Shrink {
+        program: Option<&'a str>,
+        case: &'a str,
+        reference: Option<&'a str>,
+    },
+    Watch {
+        program: Option<&'a str>,
+        case: Option<&'a str>,
+    },
+    Listen {
+        port: u16,
+    },
+    ImportSamples {
+        file: &'a str,
+        program: Option<&'a str>,
     },
     Debug {
         program: Option<&'a str>,
@@ -55,7 +103,7 @@ pub enum Subcommand<'a> {
 pub fn parse_args<'a, I: Iterator<Item = &'a str>>(
     opts: &mut Options<&'a str, I>,
 ) -> Result<Arguments<'a>, UsageError<'a>> {
-    while let Some(opt) = opts.next_opt()? {
+    if let Some(opt) = opts.next_opt()? {
         match opt {
             Opt::Short('h') | Opt::Long("help") => return Err(UsageError::Help),
             Opt::Long("version") => return Err(UsageError::Version),
@@ -70,6 +118,12 @@ pub fn parse_args<'a, I: Iterator<Item = &'a str>>(
         "debug" | "d" => parse_debug_args(opts)?,
         "run" | "r" => parse_run_args(opts)?,
         "test" | "t" => parse_test_args(opts)?,
+        "stress" | "s" => parse_stress_args(opts)?,
+        "bench" => parse_bench_args(opts)?,
+        "shrink" => parse_shrink_args(opts)?,
+        "watch" | "w" => parse_watch_args(opts)?,
+        "listen" => parse_listen_args(opts)?,
+        "import-samples" => parse_import_samples_args(opts)?,
         "cmake" => Subcommand::CMake,
         _ => return Err(UsageError::UnknownSubcommand(subcommand_name)),
     };
@@ -128,8 +182,237 @@ fn parse_run_args<'a, I: Iterator<Item = &'a str>>(
 fn parse_test_args<'a, I: Iterator<Item = &'a str>>(
     opts: &mut Options<&'a str, I>,
 ) -> Result<Subcommand<'a>, UsageError<'a>> {
+    let mut jobs = None;
+    let mut bless = false;
+    let mut samples = false;
+    let mut skip = false;
+    let mut side_by_side = false;
+    while let Some(opt) = opts.next_opt()? {
+        match opt {
+            Opt::Long("bless") => bless = true,
+            Opt::Long("samples") => samples = true,
+            Opt::Short('s') | Opt::Long("skip") => skip = true,
+            Opt::Short('y') | Opt::Long("side-by-side") => side_by_side = true,
+            Opt::Short('j') | Opt::Long("jobs") => {
+                let value = opts.value()?;
+                match value.parse() {
+                    Ok(n) if n > 0 => jobs = Some(n),
+                    _ => return Err(UsageError::InvalidValue(opt, value)),
+                }
+            }
+            _ => return Err(UsageError::UnknownOpt(opt)),
+        }
+    }
+    let program = opts.next_positional();
+    if program == Some("add") {
+        return parse_test_add_args(opts);
+    }
     Ok(Subcommand::Test {
+        program,
+        tests: opts.positionals().collect(),
+        jobs,
+        bless,
+        samples,
+        skip,
+        side_by_side,
+    })
+}
+
+fn parse_test_add_args<'a, I: Iterator<Item = &'a str>>(
+    opts: &mut Options<&'a str, I>,
+) -> Result<Subcommand<'a>, UsageError<'a>> {
+    let mut editor = false;
+    let mut delimiter = "---";
+    let mut compress = false;
+    while let Some(opt) = opts.next_opt()? {
+        match opt {
+            Opt::Short('e') | Opt::Long("editor") => editor = true,
+            Opt::Short('d') | Opt::Long("delimiter") => delimiter = opts.value()?,
+            Opt::Short('z') | Opt::Long("compress") => compress = true,
+            _ => return Err(UsageError::UnknownOpt(opt)),
+        }
+    }
+    Ok(Subcommand::TestAdd {
+        program: opts.next_positional(),
+        editor,
+        delimiter,
+        compress,
+    })
+}
+
+fn parse_bench_args<'a, I: Iterator<Item = &'a str>>(
+    opts: &mut Options<&'a str, I>,
+) -> Result<Subcommand<'a>, UsageError<'a>> {
+    let mut runs = 10;
+    let mut warmup = 2;
+    while let Some(opt) = opts.next_opt()? {
+        match opt {
+            Opt::Short('n') | Opt::Long("runs") => {
+                let value = opts.value()?;
+                match value.parse() {
+                    Ok(n) if n > 0 => runs = n,
+                    _ => return Err(UsageError::InvalidValue(opt, value)),
+                }
+            }
+            Opt::Short('w') | Opt::Long("warmup") => {
+                let value = opts.value()?;
+                match value.parse() {
+                    Ok(n) => warmup = n,
+                    _ => return Err(UsageError::InvalidValue(opt, value)),
+                }
+            }
+            _ => return Err(UsageError::UnknownOpt(opt)),
+        }
+    }
+    Ok(Subcommand::Bench {
         program: opts.next_positional(),
         tests: opts.positionals().collect(),
+        runs,
+        warmup,
+    })
+}
+
+/// Get the next option, like `Options::next_opt`, but allow positional
+/// arguments to come before it. They are added to `positionals`.
+fn next_opt_after_positionals<'a, I: Iterator<Item = &'a str>>(
+    opts: &mut Options<&'a str, I>,
+    positionals: &mut Vec<&'a str>,
+) -> Result<Option<Opt<&'a str>>, UsageError<'a>> {
+    while let Some(arg) = opts.next_arg()? {
+        match arg {
+            Arg::Short(c) => return Ok(Some(Opt::Short(c))),
+            Arg::Long(name) => return Ok(Some(Opt::Long(name))),
+            Arg::Positional(arg) => positionals.push(arg),
+        }
+    }
+    Ok(None)
+}
+
+/// Check that all of the positional arguments were used.
+fn no_more_args<'a>(mut rest: impl Iterator<Item = &'a str>) -> Result<(), UsageError<'a>> {
+    match rest.next() {
+        Some(arg) => Err(UsageError::UnexpectedArg(arg)),
+        None => Ok(()),
+    }
+}
+
+fn parse_stress_args<'a, I: Iterator<Item = &'a str>>(
+    opts: &mut Options<&'a str, I>,
+) -> Result<Subcommand<'a>, UsageError<'a>> {
+    let mut generator = None;
+    let mut reference = None;
+    let mut count = None;
+    let mut seed = 1;
+    let mut positionals = vec![];
+    while let Some(opt) = next_opt_after_positionals(opts, &mut positionals)? {
+        match opt {
+            Opt::Short('g') | Opt::Long("gen") => generator = Some(opts.value()?),
+            Opt::Short('r') | Opt::Long("ref") => reference = Some(opts.value()?),
+            Opt::Short('n') | Opt::Long("count") => {
+                let value = opts.value()?;
+                match value.parse() {
+                    Ok(n) => count = Some(n),
+                    _ => return Err(UsageError::InvalidValue(opt, value)),
+                }
+            }
+            Opt::Long("seed") => {
+                let value = opts.value()?;
+                match value.parse() {
+                    Ok(n) => seed = n,
+                    _ => return Err(UsageError::InvalidValue(opt, value)),
+                }
+            }
+            _ => return Err(UsageError::UnknownOpt(opt)),
+        }
+    }
+    let mut positionals = positionals.into_iter();
+    let program = positionals.next();
+    no_more_args(positionals)?;
+    Ok(Subcommand::Stress {
+        program,
+        generator: generator.ok_or(UsageError::MissingOpt("--gen"))?,
+        reference: reference.ok_or(UsageError::MissingOpt("--ref"))?,
+        count,
+        seed,
+    })
+}
+
+fn parse_watch_args<'a, I: Iterator<Item = &'a str>>(
+    opts: &mut Options<&'a str, I>,
+) -> Result<Subcommand<'a>, UsageError<'a>> {
+    let mut case = None;
+    while let Some(opt) = opts.next_opt()? {
+        match opt {
+            Opt::Short('c') | Opt::Long("case") => case = Some(opts.value()?),
+            _ => return Err(UsageError::UnknownOpt(opt)),
+        }
+    }
+    Ok(Subcommand::Watch {
+        program: opts.next_positional(),
+        case,
+    })
+}
+
+fn parse_listen_args<'a, I: Iterator<Item = &'a str>>(
+    opts: &mut Options<&'a str, I>,
+) -> Result<Subcommand<'a>, UsageError<'a>> {
+    // Any free port works, as long as it is one of the ports that Competitive
+    // Companion sends problems to, which can be set in its options
+    let mut port = 10043;
+    while let Some(opt) = opts.next_opt()? {
+        match opt {
+            Opt::Short('p') | Opt::Long("port") => {
+                let value = opts.value()?;
+                match value.parse() {
+                    Ok(n) => port = n,
+                    _ => return Err(UsageError::InvalidValue(opt, value)),
+                }
+            }
+            _ => return Err(UsageError::UnknownOpt(opt)),
+        }
+    }
+    Ok(Subcommand::Listen { port })
+}
+
+fn parse_import_samples_args<'a, I: Iterator<Item = &'a str>>(
+    opts: &mut Options<&'a str, I>,
+) -> Result<Subcommand<'a>, UsageError<'a>> {
+    let mut program = None;
+    let mut positionals = vec![];
+    while let Some(opt) = next_opt_after_positionals(opts, &mut positionals)? {
+        match opt {
+            Opt::Short('p') | Opt::Long("program") => program = Some(opts.value()?),
+            _ => return Err(UsageError::UnknownOpt(opt)),
+        }
+    }
+    let mut positionals = positionals.into_iter();
+    let file = positionals.next().ok_or(UsageError::MissingArg("FILE"))?;
+    no_more_args(positionals)?;
+    Ok(Subcommand::ImportSamples { file, program })
+}
+
+fn parse_shrink_args<'a, I: Iterator<Item = &'a str>>(
+    opts: &mut Options<&'a str, I>,
+) -> Result<Subcommand<'a>, UsageError<'a>> {
+    let mut reference = None;
+    let mut positionals = vec![];
+    while let Some(opt) = next_opt_after_positionals(opts, &mut positionals)? {
+        match opt {
+            Opt::Short('r') | Opt::Long("ref") => reference = Some(opts.value()?),
+            _ => return Err(UsageError::UnknownOpt(opt)),
+        }
+    }
+    // The program is optional, but comes before the case
+    let mut positionals = positionals.into_iter();
+    let (program, case) = match (positionals.next(), positionals.next()) {
+        (Some(case), None) => (None, case),
+        (Some(program), Some(case)) => (Some(program), case),
+        (None, _) => return Err(UsageError::MissingArg("CASE")),
+    };
+    no_more_args(positionals)?;
+    Ok(Subcommand::Shrink {
+        program,
+        case,
+        reference,
     })
 }
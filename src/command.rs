@@ -3,21 +3,57 @@ use std::process::{Command, ExitStatus};
 
 use crate::Program;
 
+pub use add::*;
+pub use bench::*;
+pub use bless::*;
 pub use build::*;
 pub use clean::*;
 pub use cmake::*;
 pub use debug::*;
+pub use group::*;
+pub use import::*;
 pub use init::*;
+use interact::*;
+pub use listen::*;
 pub use run::*;
+pub use shrink::*;
+pub use stress::*;
 pub use test::*;
+pub use watch::*;
 
+mod add;
+mod bench;
+mod bless;
 mod build;
 mod clean;
 mod cmake;
+mod compare;
+mod compress;
 mod debug;
+mod group;
+mod import;
 mod init;
+mod interact;
+mod layout;
+mod listen;
+mod process;
 mod run;
+#[cfg(target_os = "linux")]
+mod sandbox;
+// The sandbox is built on Linux namespaces and seccomp
+#[cfg(not(target_os = "linux"))]
+#[path = "command/sandbox_unsupported.rs"]
+mod sandbox;
+mod shrink;
+mod stress;
+mod supervise;
 mod test;
+#[cfg(target_os = "linux")]
+mod watch;
+// Watching for changes is built on inotify
+#[cfg(not(target_os = "linux"))]
+#[path = "command/watch_unsupported.rs"]
+mod watch;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum RunResult {
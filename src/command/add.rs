@@ -0,0 +1,60 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::Program;
+
+/// Split test data into the input and the expected output, at the first line
+/// that consists of the delimiter.
+pub fn split_test_data<'d>(data: &'d [u8], delimiter: &str) -> Result<(&'d [u8], &'d [u8])> {
+    let mut start = 0;
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        let end = start + line.len();
+        let content = line.strip_suffix(b"\n").unwrap_or(line);
+        let content = content.strip_suffix(b"\r").unwrap_or(content);
+        if content == delimiter.as_bytes() {
+            return Ok((&data[..start], &data[end..]));
+        }
+        start = end;
+    }
+    bail!("no line {:?} between the input and the output", delimiter);
+}
+
+/// Let the user write test data in their editor, taken from `$VISUAL` or
+/// `$EDITOR`. The file starts out with just the delimiter line, for the input
+/// to go above it and the expected output below it. Returns the contents of
+/// the file after the editor exits.
+pub fn edit_test_data(prog: &Program, delimiter: &str) -> Result<Vec<u8>> {
+    let path = prog
+        .repository()
+        .build_path()
+        .join("add")
+        .join(format!("{}.txt", prog.name()));
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("failed to create dir {:?}", parent))?;
+    }
+    fs::write(&path, format!("\n{delimiter}\n"))
+        .with_context(|| format!("failed to write file {:?}", path))?;
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // The editor may have arguments, like `code --wait`
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(&path);
+    let status = cmd
+        .status()
+        .with_context(|| format!("failed to run editor {:?}", editor))?;
+    if !status.success() {
+        bail!("editor {:?} exited with {}", editor, status);
+    }
+
+    let data = fs::read(&path).with_context(|| format!("failed to read file {:?}", path))?;
+    let _ = fs::remove_file(&path);
+    Ok(data)
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+
+use crate::command::supervise::{execute, Limits};
+use crate::command::{get_run_command, load_test_args, open_test_file, run_verdict};
+use crate::Program;
+
+/// Largest standard deviation of the timings, relative to their mean, for
+/// them to be trusted.
+const NOISE_THRESHOLD: f64 = 0.1;
+
+/// Summary of the times taken by repeated runs.
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    pub min: Duration,
+    pub median: Duration,
+    pub mean: Duration,
+    pub stddev: Duration,
+}
+
+impl Stats {
+    fn new(times: &mut [Duration]) -> Stats {
+        times.sort();
+        let n = times.len();
+        let median = if n.is_multiple_of(2) {
+            (times[n / 2 - 1] + times[n / 2]) / 2
+        } else {
+            times[n / 2]
+        };
+        let mean = times.iter().map(Duration::as_secs_f64).sum::<f64>() / n as f64;
+        let variance = times
+            .iter()
+            .map(|t| (t.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / n as f64;
+        Stats {
+            min: times[0],
+            median,
+            mean: Duration::from_secs_f64(mean),
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+        }
+    }
+
+    /// Get the standard deviation relative to the mean.
+    pub fn spread(&self) -> f64 {
+        if self.mean.is_zero() {
+            0.0
+        } else {
+            self.stddev.as_secs_f64() / self.mean.as_secs_f64()
+        }
+    }
+
+    /// Whether the times vary too much for the summary to be trusted.
+    pub fn is_noisy(&self) -> bool {
+        self.spread() > NOISE_THRESHOLD
+    }
+}
+
+/// Timings of a program on a test case.
+#[derive(Clone, Copy, Debug)]
+pub struct Benchmark {
+    pub wall: Stats,
+    pub cpu: Stats,
+}
+
+/// Time the program on a test case. The program is run `warmup` times
+/// without being timed, to fill the caches, and then `runs` times. Its output
+/// is not checked, but every run must succeed. `progress` is called with the
+/// number of timed runs done so far.
+pub fn bench(
+    prog: &Program,
+    case: &str,
+    warmup: usize,
+    runs: usize,
+    progress: &mut dyn FnMut(usize),
+) -> Result<Benchmark> {
+    if prog.interactor()?.is_some() {
+        bail!("cannot benchmark {} on an interactive problem", prog);
+    }
+
+    let limits = Limits::for_case(prog, case);
+    let args = load_test_args(prog, case)?;
+    let mut wall = vec![];
+    let mut cpu = vec![];
+    for i in 0..warmup + runs {
+        let mut cmd = get_run_command(prog);
+        cmd.args(&args);
+        let execution = execute(&mut cmd, open_test_file(prog, case, "in")?, &limits)?;
+        if let Some(status) = run_verdict(&execution, &limits, None) {
+            bail!(
+                "{} did not run successfully on test case {}: {}",
+                prog,
+                case,
+                status
+            );
+        }
+        if i >= warmup {
+            wall.push(execution.time);
+            cpu.push(execution.usage.cpu_time());
+            progress(wall.len());
+        }
+    }
+
+    Ok(Benchmark {
+        wall: Stats::new(&mut wall),
+        cpu: Stats::new(&mut cpu),
+    })
+}
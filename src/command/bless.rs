@@ -0,0 +1,67 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+use crate::command::supervise::{execute, Limits};
+use crate::command::{
+    find_test_file, get_run_command, load_test_args, open_test_file, run_verdict, test_file_path,
+    write_compressed_test_file, TestResult,
+};
+use crate::Program;
+
+/// Outcome of blessing a test case.
+pub enum Blessed {
+    /// The program's output was written to the file.
+    Written(PathBuf),
+    /// The file with the expected output was not overwritten.
+    Kept(PathBuf),
+    /// The program did not run successfully, so its output was not used.
+    Failed(Box<TestResult>),
+}
+
+/// Accept the program's output on a test case as the expected output. The
+/// expected output is written with the same compression as the existing one,
+/// or as the input if there is none. Before an existing expected output is
+/// overwritten, `confirm_overwrite` is asked with its path.
+pub fn bless(
+    prog: &Program,
+    case: &str,
+    confirm_overwrite: &mut dyn FnMut(&Path) -> bool,
+) -> Result<Blessed> {
+    if prog.interactor()?.is_some() {
+        bail!(
+            "cannot take the expected output of an interactive problem from {}",
+            prog
+        );
+    }
+
+    let limits = Limits::for_case(prog, case);
+    let mut cmd = get_run_command(prog);
+    cmd.args(load_test_args(prog, case)?);
+    let execution = execute(&mut cmd, open_test_file(prog, case, "in")?, &limits)?;
+    if let Some(status) = run_verdict(&execution, &limits, None) {
+        let result = TestResult::from_execution(execution, &limits, status, None);
+        return Ok(Blessed::Failed(Box::new(result)));
+    }
+
+    let (path, compression) = match find_test_file(prog, case, "out") {
+        Some((path, compression)) => {
+            if !confirm_overwrite(&path) {
+                return Ok(Blessed::Kept(path));
+            }
+            (path, compression)
+        }
+        None => {
+            let compression = find_test_file(prog, case, "in").and_then(|(_, c)| c);
+            let mut path = OsString::from(test_file_path(prog, case, "out"));
+            if let Some(compression) = compression {
+                path.push(compression.suffix);
+            }
+            (PathBuf::from(path), compression)
+        }
+    };
+
+    write_compressed_test_file(&path, &execution.stdout, compression)?;
+    Ok(Blessed::Written(path))
+}
@@ -5,6 +5,7 @@ use std::path::Path;
 use anyhow::Context;
 use anyhow::Result;
 
+use crate::command::{check_dir, output_dir};
 use crate::{Program, Repository};
 
 /// Clean all compiled binaries from the repository.
@@ -17,7 +18,8 @@ pub fn clean_all(repo: &Repository) -> Result<()> {
 }
 
 /// Clean the program's binaries. This deletes the debug and
-/// release binaries if they exist.
+/// release binaries if they exist, along with the files kept from
+/// its tests.
 pub fn clean(prog: &Program) -> Result<()> {
     fn try_delete_file(path: &Path) -> Result<()> {
         match fs::remove_file(path) {
@@ -26,7 +28,16 @@ pub fn clean(prog: &Program) -> Result<()> {
             Err(e) => Err(e).with_context(|| format!("failed to delete file {:?}", path)),
         }
     }
+    fn try_delete_dir(path: &Path) -> Result<()> {
+        match fs::remove_dir_all(path) {
+            Ok(()) => Ok(()),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("failed to delete dir {:?}", path)),
+        }
+    }
     try_delete_file(prog.build_debug_path())?;
     try_delete_file(prog.build_release_path())?;
+    try_delete_dir(&output_dir(prog))?;
+    try_delete_dir(&check_dir(prog))?;
     Ok(())
 }
@@ -0,0 +1,184 @@
+use crate::{Comparator, CompareMode};
+
+impl Comparator {
+    /// Check whether the actual output of a program is accepted, given the
+    /// expected output.
+    pub fn matches(&self, actual: &[u8], expected: &[u8]) -> bool {
+        match self.mode {
+            CompareMode::Exact => actual == expected,
+            CompareMode::Tokens => tokens(actual).eq(tokens(expected)),
+            CompareMode::Float => {
+                let mut act = tokens(actual);
+                let mut exp = tokens(expected);
+                loop {
+                    match (act.next(), exp.next()) {
+                        (Some(a), Some(e)) if self.floats_match(a, e) => {}
+                        (None, None) => return true,
+                        _ => return false,
+                    }
+                }
+            }
+            CompareMode::CaseInsensitive => {
+                let mut act = tokens(actual);
+                let mut exp = tokens(expected);
+                loop {
+                    match (act.next(), exp.next()) {
+                        (Some(a), Some(e)) if a.eq_ignore_ascii_case(e) => {}
+                        (None, None) => return true,
+                        _ => return false,
+                    }
+                }
+            }
+            CompareMode::UnorderedLines => {
+                let mut act = lines(actual);
+                let mut exp = lines(expected);
+                act.sort_unstable();
+                exp.sort_unstable();
+                act == exp
+            }
+        }
+    }
+
+    /// Compare two tokens, allowing numbers to differ within the tolerances.
+    fn floats_match(&self, actual: &[u8], expected: &[u8]) -> bool {
+        if actual == expected {
+            return true;
+        }
+        match (parse_float(actual), parse_float(expected)) {
+            (Some(a), Some(e)) => {
+                let diff = (a - e).abs();
+                diff <= self.absolute_tolerance || diff <= self.relative_tolerance * e.abs()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Measure how close the actual output is to an expected output, as the
+/// number of tokens that are the same in both, less the difference in their
+/// numbers of tokens. Used to pick the expected output to show a diff with.
+pub(super) fn similarity(actual: &[u8], expected: &[u8]) -> isize {
+    let same = tokens(actual)
+        .zip(tokens(expected))
+        .filter(|(a, e)| a == e)
+        .count();
+    let act_len = tokens(actual).count();
+    let exp_len = tokens(expected).count();
+    same as isize - (act_len as isize - exp_len as isize).abs()
+}
+
+/// Split the data into whitespace-separated tokens.
+fn tokens(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    data.split(u8::is_ascii_whitespace)
+        .filter(|token| !token.is_empty())
+}
+
+/// Split the data into lines, ignoring trailing whitespace on each line and
+/// blank lines at the end.
+fn lines(data: &[u8]) -> Vec<&[u8]> {
+    let mut lines: Vec<_> = data
+        .split(|&b| b == b'\n')
+        .map(|line| {
+            let end = line
+                .iter()
+                .rposition(|b| !b.is_ascii_whitespace())
+                .map_or(0, |i| i + 1);
+            &line[..end]
+        })
+        .collect();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn parse_float(token: &[u8]) -> Option<f64> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comparator(mode: CompareMode) -> Comparator {
+        Comparator {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exact() {
+        let cmp = comparator(CompareMode::Exact);
+        assert!(cmp.matches(b"1 2\n", b"1 2\n"));
+        assert!(!cmp.matches(b"1 2", b"1 2\n"));
+        assert!(!cmp.matches(b"1  2\n", b"1 2\n"));
+    }
+
+    #[test]
+    fn tokens_ignore_whitespace() {
+        let cmp = comparator(CompareMode::Tokens);
+        assert!(cmp.matches(b"1  2\r\n\n", b"1 2\n"));
+        assert!(cmp.matches(b"", b"\n \n"));
+        assert!(!cmp.matches(b"1 2 3\n", b"1 2\n"));
+        assert!(!cmp.matches(b"12\n", b"1 2\n"));
+    }
+
+    #[test]
+    fn float_tolerances() {
+        let cmp = comparator(CompareMode::Float);
+        assert!(cmp.matches(b"0.3333333\n", b"0.333333333\n"));
+        assert!(!cmp.matches(b"0.3333\n", b"0.333333333\n"));
+        // Large numbers are compared with the relative tolerance
+        assert!(cmp.matches(b"1000000001\n", b"1000000000\n"));
+        assert!(!cmp.matches(b"1000010000\n", b"1000000000\n"));
+        // Tokens that are not numbers must be identical
+        assert!(cmp.matches(b"YES 1.0\n", b"YES 1\n"));
+        assert!(!cmp.matches(b"yes 1\n", b"YES 1\n"));
+        assert!(!cmp.matches(b"1 2\n", b"1\n"));
+    }
+
+    #[test]
+    fn float_edge_cases() {
+        let cmp = comparator(CompareMode::Float);
+        assert!(cmp.matches(b"-0\n", b"0\n"));
+        assert!(cmp.matches(b"1e-9\n", b"0\n"));
+        assert!(cmp.matches(b"nan\n", b"nan\n"));
+        assert!(!cmp.matches(b"NaN\n", b"nan\n"));
+        assert!(!cmp.matches(b"inf\n", b"1e308\n"));
+        let strict = Comparator {
+            mode: CompareMode::Float,
+            absolute_tolerance: 0.0,
+            relative_tolerance: 0.0,
+        };
+        assert!(strict.matches(b"0.5\n", b"0.50\n"));
+        assert!(!strict.matches(b"0.5000001\n", b"0.5\n"));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let cmp = comparator(CompareMode::CaseInsensitive);
+        assert!(cmp.matches(b"yes\nNo\n", b"YES NO"));
+        assert!(!cmp.matches(b"yes\n", b"YES NO\n"));
+    }
+
+    #[test]
+    fn unordered_lines() {
+        let cmp = comparator(CompareMode::UnorderedLines);
+        assert!(cmp.matches(b"b\na \n\n", b"a\nb\n"));
+        assert!(cmp.matches(b"a\na\nb\n", b"a\nb\na"));
+        // Lines are not split into tokens, and duplicates are counted
+        assert!(!cmp.matches(b"a b\n", b"a  b\n"));
+        assert!(!cmp.matches(b"a\nb\n", b"a\nb\nb\n"));
+        // Blank lines are only ignored at the end
+        assert!(!cmp.matches(b"\na\n", b"a\n"));
+    }
+
+    #[test]
+    fn similarity_prefers_closer_outputs() {
+        let expected = b"1 2 3 4\n";
+        assert_eq!(similarity(expected, expected), 4);
+        assert!(similarity(b"1 2 3 5\n", expected) > similarity(b"1 2\n", expected));
+        assert!(similarity(b"1 2 3 4 5 6 7 8\n", expected) < 4);
+    }
+}
@@ -0,0 +1,76 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use bzip2::read::MultiBzDecoder;
+use bzip2::write::BzEncoder;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use xz2::read::XzDecoder;
+use xz2::write::XzEncoder;
+
+/// A compression format that test files may be stored in.
+pub(super) struct Compression {
+    /// Suffix of the compressed file, such as `.xz`.
+    pub suffix: &'static str,
+    /// Wrap the compressed file in a decoder.
+    pub decoder: fn(File) -> io::Result<Box<dyn Read + Send>>,
+    /// Compress the data into the file.
+    pub encode: fn(File, &[u8]) -> io::Result<()>,
+}
+
+/// The supported compression formats, in the order in which they are tried
+/// when looking for a test file.
+pub(super) const COMPRESSIONS: &[Compression] = &[
+    Compression {
+        suffix: ".xz",
+        decoder: |f| Ok(Box::new(XzDecoder::new(f))),
+        encode: |f, data| {
+            let mut encoder = XzEncoder::new(f, 6);
+            encoder.write_all(data)?;
+            encoder.finish()?;
+            Ok(())
+        },
+    },
+    Compression {
+        suffix: ".zst",
+        decoder: |f| Ok(Box::new(zstd::Decoder::new(f)?)),
+        encode: |f, data| {
+            let mut encoder = zstd::Encoder::new(f, 0)?;
+            encoder.write_all(data)?;
+            encoder.finish()?;
+            Ok(())
+        },
+    },
+    Compression {
+        suffix: ".gz",
+        decoder: |f| Ok(Box::new(MultiGzDecoder::new(f))),
+        encode: |f, data| {
+            let mut encoder = GzEncoder::new(f, flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?;
+            Ok(())
+        },
+    },
+    Compression {
+        suffix: ".bz2",
+        decoder: |f| Ok(Box::new(MultiBzDecoder::new(f))),
+        encode: |f, data| {
+            let mut encoder = BzEncoder::new(f, bzip2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?;
+            Ok(())
+        },
+    },
+];
+
+/// The format used when compressing new test files.
+pub(super) const XZ: &Compression = &COMPRESSIONS[0];
+
+/// Remove the suffix of a compression format from the file name, if it has
+/// one.
+pub(super) fn strip_compression_suffix(name: &str) -> &str {
+    COMPRESSIONS
+        .iter()
+        .find_map(|c| name.strip_suffix(c.suffix))
+        .unwrap_or(name)
+}
@@ -0,0 +1,65 @@
+use anyhow::{bail, Result};
+
+use crate::command::get_test_cases;
+use crate::Program;
+
+/// A group of test cases that is scored as a whole, like a subtask.
+#[derive(Clone, Debug)]
+pub struct Group {
+    pub name: String,
+    /// Points awarded if every case in the group passes.
+    pub points: f64,
+    pub cases: Vec<String>,
+}
+
+/// Get the directory that a test case is in, relative to the test directory.
+fn case_dir(case: &str) -> &str {
+    case.rfind('/').map_or("", |i| &case[..i])
+}
+
+/// Split the test cases into groups. Each subdirectory of the test directory
+/// is a group, and the problem's `tests.toml` file may give groups points or
+/// list their cases explicitly. Cases outside of any group are not included.
+/// The groups are ordered by their first case.
+pub fn get_test_groups(prog: &Program, cases: &[String]) -> Result<Vec<Group>> {
+    let config = &prog.problem_config().groups;
+    let mut groups: Vec<Group> = vec![];
+
+    for case in cases {
+        let dir = case_dir(case);
+        if dir.is_empty() || config.get(dir).is_some_and(|g| g.cases.is_some()) {
+            continue;
+        }
+        match groups.iter_mut().find(|g| g.name == dir) {
+            Some(group) => group.cases.push(case.clone()),
+            None => groups.push(Group {
+                name: dir.to_string(),
+                points: config.get(dir).map_or(0.0, |g| g.points),
+                cases: vec![case.clone()],
+            }),
+        }
+    }
+
+    let all_cases = get_test_cases(prog)?;
+    for (name, group) in config {
+        if let Some(group_cases) = &group.cases {
+            if let Some(case) = group_cases.iter().find(|case| !all_cases.contains(case)) {
+                bail!("test case {:?} of group {:?} does not exist", case, name);
+            }
+            groups.push(Group {
+                name: name.clone(),
+                points: group.points,
+                // Only keep the cases that are being run
+                cases: group_cases
+                    .iter()
+                    .filter(|case| cases.contains(case))
+                    .cloned()
+                    .collect(),
+            });
+        }
+    }
+
+    groups.retain(|g| !g.cases.is_empty());
+    groups.sort_by_key(|g| cases.iter().position(|case| g.cases.contains(case)));
+    Ok(groups)
+}
@@ -0,0 +1,305 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use scraper::{ElementRef, Html, Node, Selector};
+
+use crate::command::{add_test_case, mark_sample_cases, Sample};
+use crate::Program;
+
+/// A parser for the sample markup of a judge's problem pages.
+struct Site {
+    name: &'static str,
+    parse: fn(&Html) -> Vec<Sample>,
+}
+
+/// The supported sites, in the order in which they are tried.
+const SITES: &[Site] = &[
+    Site {
+        name: "Codeforces",
+        parse: parse_codeforces,
+    },
+    Site {
+        name: "AtCoder",
+        parse: parse_atcoder,
+    },
+    Site {
+        name: "Kattis",
+        parse: parse_kattis,
+    },
+    Site {
+        name: "CSES",
+        parse: parse_cses,
+    },
+];
+
+fn selector(s: &str) -> Selector {
+    Selector::parse(s).unwrap()
+}
+
+/// Get the text of a `<pre>` element. Line breaks may be written as `<br>`
+/// or as one `<div>` per line, like on Codeforces.
+fn pre_text(pre: ElementRef) -> String {
+    let mut text = String::new();
+    for node in pre.descendants() {
+        match node.value() {
+            Node::Text(t) => text.push_str(t),
+            Node::Element(e) if e.name() == "br" => text.push('\n'),
+            Node::Element(e) if e.name() == "div" && !text.is_empty() && !text.ends_with('\n') => {
+                text.push('\n')
+            }
+            _ => {}
+        }
+    }
+    let mut text = text.trim_end().to_string();
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text
+}
+
+/// Pair up the inputs and outputs in the order in which they appear.
+fn zip_samples(inputs: Vec<String>, outputs: Vec<String>) -> Vec<Sample> {
+    inputs
+        .into_iter()
+        .zip(outputs)
+        .map(|(input, output)| Sample { input, output })
+        .collect()
+}
+
+/// `<div class="input"><pre>` and `<div class="output"><pre>`, inside
+/// `<div class="sample-test">`.
+fn parse_codeforces(html: &Html) -> Vec<Sample> {
+    let input = selector("div.sample-test div.input pre");
+    let output = selector("div.sample-test div.output pre");
+    zip_samples(
+        html.select(&input).map(pre_text).collect(),
+        html.select(&output).map(pre_text).collect(),
+    )
+}
+
+/// `<h3>Sample Input 1</h3><pre>` and `<h3>Sample Output 1</h3><pre>`, each
+/// in a `<section>`. Statements are in both Japanese and English, so only the
+/// English samples are used, unless there are none.
+fn parse_atcoder(html: &Html) -> Vec<Sample> {
+    let section = selector("section");
+    let heading = selector("h3");
+    let pre = selector("pre");
+    let samples = |input_title: &str, output_title: &str| {
+        let mut inputs = vec![];
+        let mut outputs = vec![];
+        for section in html.select(&section) {
+            let (title, pre) = match (section.select(&heading).next(), section.select(&pre).next())
+            {
+                (Some(h), Some(pre)) => (h.text().collect::<String>(), pre),
+                _ => continue,
+            };
+            if title.trim().starts_with(input_title) {
+                inputs.push(pre_text(pre));
+            } else if title.trim().starts_with(output_title) {
+                outputs.push(pre_text(pre));
+            }
+        }
+        zip_samples(inputs, outputs)
+    };
+    let english = samples("Sample Input", "Sample Output");
+    if english.is_empty() {
+        samples("入力例", "出力例")
+    } else {
+        english
+    }
+}
+
+/// `<table class="sample">`, with the input and output as the first two
+/// `<pre>` elements.
+fn parse_kattis(html: &Html) -> Vec<Sample> {
+    let table = selector("table.sample");
+    let pre = selector("pre");
+    html.select(&table)
+        .filter_map(|table| {
+            let mut pres = table.select(&pre).map(pre_text);
+            Some(Sample {
+                input: pres.next()?,
+                output: pres.next()?,
+            })
+        })
+        .collect()
+}
+
+/// `<p>Input:</p><pre>` and `<p>Output:</p><pre>`.
+fn parse_cses(html: &Html) -> Vec<Sample> {
+    let pre = selector("pre");
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    for pre in html.select(&pre) {
+        let label = pre
+            .prev_siblings()
+            .filter_map(ElementRef::wrap)
+            .next()
+            .filter(|e| e.value().name() == "p")
+            .map(|e| e.text().collect::<String>());
+        match label.as_deref().map(str::trim) {
+            Some("Input:") => inputs.push(pre_text(pre)),
+            Some("Output:") => outputs.push(pre_text(pre)),
+            _ => {}
+        }
+    }
+    zip_samples(inputs, outputs)
+}
+
+/// Import the samples from a saved problem statement page into the program's
+/// test directory, as the test cases `sample-1`, `sample-2`, etc., which are
+/// marked as samples in its `tests.toml` file. Returns the name of the site
+/// the page is from, and the names of the new test cases.
+pub fn import_samples(prog: &Program, path: &Path) -> Result<(&'static str, Vec<String>)> {
+    let html = fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+    let html = Html::parse_document(&html);
+    let (site, samples) = match SITES
+        .iter()
+        .map(|site| (site.name, (site.parse)(&html)))
+        .find(|(_, samples)| !samples.is_empty())
+    {
+        Some(found) => found,
+        None => bail!("no samples found in {:?}", path),
+    };
+
+    let mut cases = vec![];
+    for sample in samples {
+        let case = add_test_case(
+            prog,
+            "sample-",
+            sample.input.as_bytes(),
+            sample.output.as_bytes(),
+            false,
+        )?;
+        cases.push(case);
+    }
+    mark_sample_cases(prog, &cases)?;
+    Ok((site, cases))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+    use crate::{Config, Repository};
+
+    fn parse(parse: fn(&Html) -> Vec<Sample>, html: &str) -> Vec<(String, String)> {
+        parse(&Html::parse_document(html))
+            .into_iter()
+            .map(|s| (s.input, s.output))
+            .collect()
+    }
+
+    fn pair(input: &str, output: &str) -> (String, String) {
+        (input.to_string(), output.to_string())
+    }
+
+    #[test]
+    fn codeforces() {
+        // Older problems use <br>, newer ones one <div> per line
+        let html = r#"<div class="sample-test">
+            <div class="input"><div class="title">Input</div><pre>3<br/>1 2 3<br/></pre></div>
+            <div class="output"><div class="title">Output</div><pre>6
+</pre></div>
+            <div class="input"><div class="title">Input</div><pre><div class="test-example-line">1</div><div class="test-example-line">5</div></pre></div>
+            <div class="output"><div class="title">Output</div><pre>5</pre></div>
+        </div>"#;
+        assert_eq!(
+            parse(parse_codeforces, html),
+            [pair("3\n1 2 3\n", "6\n"), pair("1\n5\n", "5\n")]
+        );
+    }
+
+    #[test]
+    fn atcoder() {
+        let html = r#"
+            <span class="lang-ja">
+            <div class="part"><section><h3>入力例 1</h3><pre>1 2
+</pre></section></div>
+            <div class="part"><section><h3>出力例 1</h3><pre>3
+</pre></section></div>
+            </span>
+            <span class="lang-en">
+            <div class="part"><section><h3>Input</h3><pre>A B</pre></section></div>
+            <div class="part"><section><h3>Sample Input 1</h3><pre>1 2
+</pre></section></div>
+            <div class="part"><section><h3>Sample Output 1</h3><pre>3
+</pre><p>1 + 2 = 3.</p></section></div>
+            </span>"#;
+        assert_eq!(parse(parse_atcoder, html), [pair("1 2\n", "3\n")]);
+
+        let japanese = r#"
+            <section><h3>入力例 1 <span class="btn">Copy</span></h3><pre>1 2</pre></section>
+            <section><h3>出力例 1 <span class="btn">Copy</span></h3><pre>3</pre></section>"#;
+        assert_eq!(parse(parse_atcoder, japanese), [pair("1 2\n", "3\n")]);
+    }
+
+    #[test]
+    fn kattis() {
+        let html = r#"<table class="sample">
+            <tr><th>Sample Input 1</th><th>Sample Output 1</th></tr>
+            <tr><td><pre>2
+1 1
+</pre></td><td><pre>2
+</pre></td></tr>
+        </table>
+        <table class="sample"><tr><td><pre>0</pre></td></tr></table>"#;
+        assert_eq!(parse(parse_kattis, html), [pair("2\n1 1\n", "2\n")]);
+    }
+
+    #[test]
+    fn cses() {
+        let html = r#"<div class="md">
+            <p>Input:</p><pre>5
+3 2 1</pre>
+            <p>Output:</p><pre>6</pre>
+            <p>Constraints</p><pre>1 &lt;= n</pre>
+        </div>"#;
+        assert_eq!(parse(parse_cses, html), [pair("5\n3 2 1\n", "6\n")]);
+    }
+
+    #[test]
+    fn no_samples() {
+        let html = "<html><body><pre>1 2</pre></body></html>";
+        for site in SITES {
+            assert!(parse(site.parse, html).is_empty(), "{}", site.name);
+        }
+    }
+
+    #[test]
+    fn import_marks_samples() {
+        let root = std::env::temp_dir()
+            .canonicalize()
+            .unwrap()
+            .join(format!("coman-import-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/a.cpp"), "").unwrap();
+        let page = root.join("a.html");
+        fs::write(
+            &page,
+            r#"<p>Input:</p><pre>1</pre><p>Output:</p><pre>2</pre>"#,
+        )
+        .unwrap();
+        let repo = Repository::new(&root, Config::default());
+
+        let prog = repo.get_program(root.join("src/a.cpp")).unwrap();
+        let (site, cases) = import_samples(&prog, &page).unwrap();
+        assert_eq!(site, "CSES");
+        assert_eq!(cases, ["sample-1"]);
+        let (_, cases) = import_samples(&prog, &page).unwrap();
+        assert_eq!(cases, ["sample-2"]);
+
+        let prog = repo.get_program(root.join("src/a.cpp")).unwrap();
+        let config = &prog.problem_config().cases;
+        assert_eq!(config.len(), 2);
+        assert!(config.iter().all(|c| c.sample));
+        assert_eq!(
+            fs::read_to_string(root.join("test/a/sample-2.out")).unwrap(),
+            "2\n"
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
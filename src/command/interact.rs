@@ -0,0 +1,274 @@
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::process::{ChildStderr, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+
+use crate::command::process::{Exit, GroupHandle, ProcessGroup, Waiter};
+use crate::command::supervise::{capture, is_forbidden_syscall, Limits};
+use crate::command::{
+    check_dir, get_run_command, judge_verdict, load_test_args, uncompressed_optional_test_file,
+    uncompressed_test_file, RunResult, TestResult, TestStatus,
+};
+use crate::Program;
+
+/// Direction of the data recorded in a transcript.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    /// Data written by the solution and read by the interactor.
+    FromSolution,
+    /// Data written by the interactor and read by the solution.
+    ToSolution,
+}
+
+/// A log of the data exchanged between the solution and the interactor.
+/// Lines written by the solution are prefixed with `> `, and lines read by
+/// the solution are prefixed with `< `.
+struct Transcript {
+    file: File,
+    last: Option<Direction>,
+    at_line_start: bool,
+}
+
+impl Transcript {
+    fn record(&mut self, dir: Direction, data: &[u8]) -> io::Result<()> {
+        if self.last != Some(dir) && !self.at_line_start {
+            // The other side interrupted a partial line
+            self.file.write_all(b"\n")?;
+            self.at_line_start = true;
+        }
+        self.last = Some(dir);
+        let prefix: &[u8] = match dir {
+            Direction::FromSolution => b"> ",
+            Direction::ToSolution => b"< ",
+        };
+        for line in data.split_inclusive(|&b| b == b'\n') {
+            if self.at_line_start {
+                self.file.write_all(prefix)?;
+            }
+            self.file.write_all(line)?;
+            self.at_line_start = line.ends_with(b"\n");
+        }
+        Ok(())
+    }
+}
+
+/// Copy data from one process to another, recording it in the transcript.
+/// Stops when the reader reaches EOF or the writer's pipe is closed, or
+/// after `limit` bytes, in which case the writer's process group is killed.
+/// Returns whether the limit was exceeded.
+fn relay(
+    mut from: impl Read,
+    mut to: impl Write,
+    transcript: &Mutex<Transcript>,
+    dir: Direction,
+    limit: Option<u64>,
+    writer: GroupHandle,
+) -> io::Result<bool> {
+    let mut buf = [0; 8192];
+    let mut total = 0;
+    loop {
+        let n = match from.read(&mut buf) {
+            Ok(0) => return Ok(false),
+            Ok(n) => n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let mut data = &buf[..n];
+        let exceeded = limit.filter(|&limit| total + n as u64 > limit);
+        if let Some(limit) = exceeded {
+            data = &data[..(limit - total) as usize];
+        }
+        total += data.len() as u64;
+        transcript.lock().unwrap().record(dir, data)?;
+        match to.write_all(data).and_then(|()| to.flush()) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == ErrorKind::BrokenPipe => return Ok(false),
+            Err(e) => return Err(e),
+        }
+        if exceeded.is_some() {
+            writer.signal(libc::SIGKILL);
+            return Ok(true);
+        }
+    }
+}
+
+/// Capture the stderr of a process in its group in the background, up to
+/// the limit.
+fn capture_stderr(
+    stderr: ChildStderr,
+    limit: Option<u64>,
+    group: &ProcessGroup,
+) -> JoinHandle<io::Result<(Vec<u8>, bool)>> {
+    let handle = group.handle();
+    thread::spawn(move || capture(stderr, limit, handle))
+}
+
+/// Wait for the child to exit until the deadline, terminating its process
+/// group if it is still running then. Returns whether the child was killed.
+fn finish(group: &ProcessGroup, waiter: &Waiter, deadline: Instant) -> Result<(Exit, bool)> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    match waiter.wait_timeout(remaining)? {
+        Some(exit) => Ok((exit, false)),
+        None => {
+            let exit = group
+                .terminate(waiter)
+                .context("failed to wait for child process")?;
+            Ok((exit, true))
+        }
+    }
+}
+
+/// Test the program on an interactive problem. The program's stdin and
+/// stdout are connected to the interactor, which is called with testlib-style
+/// arguments: the input file, a file for its own output, and the expected
+/// answer, if there is one. The verdict is taken from the interactor's exit
+/// status.
+pub(super) fn test_interactive(
+    prog: &Program,
+    interactor: &Program,
+    case: &str,
+) -> Result<TestResult> {
+    let limits = Limits::for_case(prog, case);
+    let dir = check_dir(prog);
+    let in_path = uncompressed_test_file(prog, case, "in", &dir)?;
+    let out_path = dir.join(format!("{case}.actual"));
+    let transcript_path = dir.join(format!("{case}.transcript"));
+    if let Some(parent) = transcript_path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("failed to create dir {:?}", parent))?;
+    }
+    let transcript = Arc::new(Mutex::new(Transcript {
+        file: File::create(&transcript_path)
+            .with_context(|| format!("failed to create file {:?}", transcript_path))?,
+        last: None,
+        at_line_start: true,
+    }));
+
+    // Start the interactor first, so that it is ready when the program starts
+    let mut int_cmd = get_run_command(interactor);
+    int_cmd.arg(&in_path).arg(&out_path);
+    if let Some(ans_path) = uncompressed_optional_test_file(prog, case, "out", &dir)? {
+        int_cmd.arg(ans_path);
+    }
+    int_cmd.stdin(Stdio::piped());
+    int_cmd.stdout(Stdio::piped());
+    int_cmd.stderr(Stdio::piped());
+    let int_slot = ProcessGroup::isolate(&mut int_cmd);
+    let mut int_child = int_cmd
+        .spawn()
+        .with_context(|| format!("failed to run interactor {:?}", int_cmd))?;
+    let int_group = ProcessGroup::new(&int_child, int_slot);
+    let int_waiter = Waiter::new(&int_child);
+
+    let mut cmd = get_run_command(prog);
+    cmd.args(load_test_args(prog, case)?);
+    cmd.stdin(Stdio::piped());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    let _scratch = limits.apply(&mut cmd)?;
+    let slot = ProcessGroup::isolate(&mut cmd);
+    let begin = Instant::now();
+    let mut child = match cmd.spawn() {
+        Ok(c) => c,
+        Err(e) => {
+            let _ = int_group.terminate(&int_waiter);
+            return Err(e).with_context(|| format!("failed to run command {:?}", cmd));
+        }
+    };
+    let group = ProcessGroup::new(&child, slot);
+    let waiter = Waiter::new(&child);
+
+    // Connect the two processes through the transcript
+    let sol_out = child.stdout.take().unwrap();
+    let int_in = int_child.stdin.take().unwrap();
+    let t = Arc::clone(&transcript);
+    let (output_limit, handle) = (limits.output, group.handle());
+    let from_thread = thread::spawn(move || {
+        relay(
+            sol_out,
+            int_in,
+            &t,
+            Direction::FromSolution,
+            output_limit,
+            handle,
+        )
+    });
+    let int_out = int_child.stdout.take().unwrap();
+    let sol_in = child.stdin.take().unwrap();
+    let t = Arc::clone(&transcript);
+    let handle = int_group.handle();
+    let to_thread =
+        thread::spawn(move || relay(int_out, sol_in, &t, Direction::ToSolution, None, handle));
+    let err_thread = capture_stderr(child.stderr.take().unwrap(), limits.output, &group);
+    let int_err_thread = capture_stderr(int_child.stderr.take().unwrap(), None, &int_group);
+
+    // Wait for both processes, up to the deadline
+    let deadline = begin + limits.wall_deadline();
+    let (exit, killed) = finish(&group, &waiter, deadline)?;
+    let (int_exit, _) = finish(&int_group, &int_waiter, deadline)?;
+    // Kill anything that either of them left running, which could keep the
+    // relaying threads from finishing
+    drop(group);
+    drop(int_group);
+    let time = exit.time - begin;
+    let counted_time = limits.counted_time(time, &exit.usage);
+
+    // Let the threads finish
+    let stdout_exceeded = from_thread
+        .join()
+        .unwrap()
+        .context("error in solution output relaying thread")?;
+    to_thread
+        .join()
+        .unwrap()
+        .context("error in interactor output relaying thread")?;
+    let (stderr, stderr_exceeded) = err_thread
+        .join()
+        .unwrap()
+        .context("error in stderr capturing thread")?;
+    let (int_stderr, _) = int_err_thread
+        .join()
+        .unwrap()
+        .context("error in interactor stderr capturing thread")?;
+
+    let run_status: RunResult = exit.status.into();
+    let status = if stdout_exceeded || stderr_exceeded {
+        TestStatus::OutputLimit
+    } else if killed
+        || run_status == RunResult::Signal(libc::SIGXCPU)
+        || counted_time >= limits.hard_timeout
+    {
+        TestStatus::Timeout
+    } else if is_forbidden_syscall(&run_status, &limits) {
+        TestStatus::ForbiddenSyscall
+    } else if limits
+        .memory
+        .is_some_and(|limit| exit.usage.peak_memory > limit)
+    {
+        TestStatus::MemoryLimit
+    } else {
+        match judge_verdict(int_exit.status.into()) {
+            TestStatus::Pass if !run_status.is_success() => TestStatus::Crash(run_status),
+            verdict => verdict,
+        }
+    };
+
+    Ok(TestResult {
+        status,
+        time,
+        user_time: exit.usage.user_time,
+        system_time: exit.usage.system_time,
+        timeout: counted_time >= limits.soft_timeout,
+        memory: exit.usage.peak_memory,
+        stderr,
+        message: Some(String::from_utf8_lossy(&int_stderr).trim().to_string()),
+        mismatch: None,
+        output_name: None,
+        transcript: Some(transcript_path),
+        description: prog.case_config(case).and_then(|c| c.description.clone()),
+        actual_path: None,
+    })
+}
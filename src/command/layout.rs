@@ -0,0 +1,286 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::command::compress::{strip_compression_suffix, COMPRESSIONS};
+use crate::TestLayout;
+
+/// Extensions of the optional files that accompany a test case's input.
+const SIDECAR_EXTENSIONS: &[&str] = &["args", "code", "err"];
+
+impl TestLayout {
+    /// Get the layout of the test directory, detecting it from the files in
+    /// the directory if the layout is `Auto`. A directory without any test
+    /// files uses the `Coman` layout.
+    pub fn resolve(self, dir: &Path) -> TestLayout {
+        if self != TestLayout::Auto {
+            return self;
+        }
+
+        // Inputs at the top level take precedence over any subdirectories,
+        // which may be test groups named like those of the other layouts
+        let mut numbered = false;
+        for ent in fs::read_dir(dir).into_iter().flatten().flatten() {
+            if let Ok(name) = ent.file_name().into_string() {
+                let name = strip_compression_suffix(&name);
+                if name.ends_with(".in") {
+                    return TestLayout::Coman;
+                } else if !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) {
+                    numbered = true;
+                }
+            }
+        }
+        if dir.join("data").is_dir() {
+            TestLayout::Kattis
+        } else if dir.join("input").is_dir() && dir.join("output").is_dir() {
+            TestLayout::Folders
+        } else if numbered {
+            TestLayout::Polygon
+        } else {
+            TestLayout::Coman
+        }
+    }
+
+    /// Get the directory, relative to the test directory, that contains the
+    /// input files, and how deeply they may be nested in it. Subdirectories
+    /// are test groups.
+    pub(super) fn input_dir(self) -> (&'static str, usize) {
+        match self {
+            TestLayout::Auto | TestLayout::Coman => ("", usize::MAX),
+            TestLayout::Polygon => ("", 1),
+            TestLayout::Kattis => ("data", usize::MAX),
+            TestLayout::Folders => ("input", 1),
+        }
+    }
+
+    /// Get the path of a test file, relative to the test directory `dir` and
+    /// without any compression suffix. The extension is the one that the
+    /// file has in the `Coman` layout.
+    pub fn file_path(self, dir: &Path, case: &str, extension: &str) -> PathBuf {
+        let output = extension.strip_prefix("out");
+        let path = match self {
+            TestLayout::Auto | TestLayout::Coman => format!("{case}.{extension}"),
+            TestLayout::Kattis => match output {
+                Some(rest) => format!("data/{case}.ans{rest}"),
+                None => format!("data/{case}.{extension}"),
+            },
+            TestLayout::Polygon => match output {
+                _ if extension == "in" => case.to_string(),
+                Some(rest) => format!("{case}.a{rest}"),
+                None => format!("{case}.{extension}"),
+            },
+            TestLayout::Folders => match output {
+                _ if extension == "in" => format!("input/{case}"),
+                Some(rest) => format!("output/{}{rest}", folders_output_name(dir, case)),
+                None => format!("input/{case}.{extension}"),
+            },
+        };
+        PathBuf::from(path)
+    }
+
+    /// Get the name of the test case whose input file is at the path,
+    /// relative to the test directory and without any compression suffix.
+    /// Returns `None` if the file is not an input file.
+    pub(super) fn case_name(self, path: &Path) -> Option<String> {
+        let path = path.to_str()?;
+        match self {
+            TestLayout::Auto | TestLayout::Coman => path.strip_suffix(".in").map(str::to_string),
+            TestLayout::Kattis => path
+                .strip_prefix("data/")?
+                .strip_suffix(".in")
+                .map(str::to_string),
+            TestLayout::Polygon => {
+                if path.contains('.') {
+                    None
+                } else {
+                    Some(path.to_string())
+                }
+            }
+            TestLayout::Folders => {
+                let name = path.strip_prefix("input/")?;
+                // The other files of the cases are next to the inputs
+                let extension = Path::new(name).extension().and_then(|e| e.to_str());
+                if extension.is_some_and(|e| SIDECAR_EXTENSIONS.contains(&e)) {
+                    None
+                } else {
+                    Some(name.to_string())
+                }
+            }
+        }
+    }
+}
+
+/// Get the name of the output file of a case in the `Folders` layout. The
+/// output usually has the same name as the input, but it may also be named
+/// after it, like `output/output00.txt` for `input/input00.txt`.
+fn folders_output_name(dir: &Path, case: &str) -> String {
+    let same = dir.join("output").join(case).into_os_string();
+    let exists = COMPRESSIONS
+        .iter()
+        .map(|c| c.suffix)
+        .chain([""])
+        .any(|suffix| {
+            let mut path = same.clone();
+            path.push(suffix);
+            Path::new(&path).is_file()
+        });
+    match case.strip_prefix("input") {
+        Some(rest) if !exists => format!("output{rest}"),
+        _ => case.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    fn case_name(layout: TestLayout, path: &str) -> Option<String> {
+        layout.case_name(Path::new(path))
+    }
+
+    #[test]
+    fn coman_paths() {
+        let (layout, dir) = (TestLayout::Coman, Path::new("/nonexistent"));
+        assert_eq!(layout.file_path(dir, "1", "in"), Path::new("1.in"));
+        assert_eq!(layout.file_path(dir, "g/1", "out"), Path::new("g/1.out"));
+        assert_eq!(layout.file_path(dir, "1", "out.2"), Path::new("1.out.2"));
+        assert_eq!(case_name(layout, "g/1.in").as_deref(), Some("g/1"));
+        assert_eq!(case_name(layout, "1.out"), None);
+        assert_eq!(case_name(layout, "1.in.args"), None);
+    }
+
+    #[test]
+    fn kattis_paths() {
+        let (layout, dir) = (TestLayout::Kattis, Path::new("/nonexistent"));
+        assert_eq!(
+            layout.file_path(dir, "sample/1", "in"),
+            Path::new("data/sample/1.in")
+        );
+        assert_eq!(
+            layout.file_path(dir, "sample/1", "out"),
+            Path::new("data/sample/1.ans")
+        );
+        assert_eq!(
+            layout.file_path(dir, "sample/1", "out.2"),
+            Path::new("data/sample/1.ans.2")
+        );
+        assert_eq!(
+            layout.file_path(dir, "sample/1", "args"),
+            Path::new("data/sample/1.args")
+        );
+        assert_eq!(
+            case_name(layout, "data/secret/1.in").as_deref(),
+            Some("secret/1")
+        );
+        assert_eq!(case_name(layout, "data/secret/1.ans"), None);
+        assert_eq!(case_name(layout, "1.in"), None);
+    }
+
+    #[test]
+    fn polygon_paths() {
+        let (layout, dir) = (TestLayout::Polygon, Path::new("/nonexistent"));
+        assert_eq!(layout.file_path(dir, "01", "in"), Path::new("01"));
+        assert_eq!(layout.file_path(dir, "01", "out"), Path::new("01.a"));
+        assert_eq!(layout.file_path(dir, "01", "out.2"), Path::new("01.a.2"));
+        assert_eq!(layout.file_path(dir, "01", "args"), Path::new("01.args"));
+        assert_eq!(case_name(layout, "01").as_deref(), Some("01"));
+        assert_eq!(case_name(layout, "01.a"), None);
+        assert_eq!(case_name(layout, "tests.toml"), None);
+    }
+
+    #[test]
+    fn folders_paths() {
+        let layout = TestLayout::Folders;
+        let dir = &test_dir("folders-paths", &[]);
+        assert_eq!(
+            layout.file_path(dir, "a.txt", "in"),
+            Path::new("input/a.txt")
+        );
+        assert_eq!(
+            layout.file_path(dir, "a.txt", "out"),
+            Path::new("output/a.txt")
+        );
+        assert_eq!(
+            layout.file_path(dir, "a.txt", "args"),
+            Path::new("input/a.txt.args")
+        );
+        assert_eq!(case_name(layout, "input/a.txt").as_deref(), Some("a.txt"));
+        assert_eq!(case_name(layout, "input/a.txt.args"), None);
+        assert_eq!(case_name(layout, "input/a.txt.code"), None);
+        assert_eq!(case_name(layout, "input/a.txt.err"), None);
+        assert_eq!(case_name(layout, "output/a.txt"), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn folders_output_names() {
+        let layout = TestLayout::Folders;
+        let dir = &test_dir(
+            "folders-outputs",
+            &[
+                "output/output00.txt",
+                "output/input01.txt.gz",
+                "output/x-input.txt",
+            ],
+        );
+        // Named after the input, unless there is one with the same name
+        assert_eq!(
+            layout.file_path(dir, "input00.txt", "out"),
+            Path::new("output/output00.txt")
+        );
+        assert_eq!(
+            layout.file_path(dir, "input00.txt", "out.1"),
+            Path::new("output/output00.txt.1")
+        );
+        assert_eq!(
+            layout.file_path(dir, "input01.txt", "out"),
+            Path::new("output/input01.txt")
+        );
+        assert_eq!(
+            layout.file_path(dir, "x-input.txt", "out"),
+            Path::new("output/x-input.txt")
+        );
+        assert_eq!(
+            layout.file_path(dir, "input-input.txt", "out"),
+            Path::new("output/output-input.txt")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Make an empty test directory with the files in it.
+    fn test_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("coman-layout-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn resolve_detects_layouts() {
+        let cases: &[(&str, &[&str], TestLayout)] = &[
+            ("empty", &[], TestLayout::Coman),
+            ("coman", &["1.in", "1.out", "tests.toml"], TestLayout::Coman),
+            ("coman-xz", &["1.in.xz", "1.out.xz"], TestLayout::Coman),
+            ("kattis", &["data/sample/1.in"], TestLayout::Kattis),
+            ("coman-data", &["1.in", "data/1.in"], TestLayout::Coman),
+            ("polygon", &["01", "01.a"], TestLayout::Polygon),
+            ("polygon-gz", &["01.gz", "01.a.gz"], TestLayout::Polygon),
+            ("folders", &["input/a", "output/a"], TestLayout::Folders),
+        ];
+        for &(name, files, expected) in cases {
+            let dir = test_dir(name, files);
+            assert_eq!(TestLayout::Auto.resolve(&dir), expected, "{}", name);
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        assert_eq!(
+            TestLayout::Kattis.resolve(Path::new("/nonexistent")),
+            TestLayout::Kattis
+        );
+    }
+}
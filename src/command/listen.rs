@@ -0,0 +1,351 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use tiny_http::{Method, Response, Server};
+use toml::value::{Table, Value};
+
+use crate::command::{test_file_path, write_test_file};
+use crate::{Program, Repository};
+
+/// A problem sent by the Competitive Companion browser extension. Only the
+/// fields used by coman are listed.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Problem {
+    pub name: String,
+    pub url: String,
+    /// Time limit in milliseconds.
+    pub time_limit: Option<u64>,
+    /// Memory limit in megabytes.
+    pub memory_limit: Option<u64>,
+    pub tests: Vec<Sample>,
+}
+
+/// A sample test from the problem statement.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Sample {
+    pub input: String,
+    pub output: String,
+}
+
+/// A server that receives problems from Competitive Companion.
+pub struct Listener {
+    server: Server,
+}
+
+impl Listener {
+    /// Listen on the port on the local machine.
+    pub fn bind(port: u16) -> Result<Listener> {
+        let server = Server::http(("127.0.0.1", port))
+            .map_err(|e| anyhow!(e))
+            .with_context(|| format!("failed to listen on port {}", port))?;
+        Ok(Listener { server })
+    }
+
+    /// Wait for the next problem. Requests that do not contain a problem are
+    /// answered with an error and returned as one.
+    pub fn receive(&self) -> Result<Problem> {
+        let mut request = self.server.recv().context("failed to receive request")?;
+        let mut body = String::new();
+        let problem = if *request.method() != Method::Post {
+            Err(anyhow!("unexpected {} request", request.method()))
+        } else if let Err(e) = request.as_reader().read_to_string(&mut body) {
+            Err(e).context("failed to read request")
+        } else {
+            serde_json::from_str(&body).context("failed to parse problem")
+        };
+        let status = if problem.is_ok() { 200 } else { 400 };
+        request
+            .respond(Response::empty(status))
+            .context("failed to respond to request")?;
+        problem
+    }
+}
+
+/// Turn the problem's name into a file name, like `a-watermelon` for
+/// `A. Watermelon`.
+fn file_stem(name: &str) -> String {
+    let mut stem = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            stem.extend(c.to_lowercase());
+        } else if !stem.is_empty() && !stem.ends_with('-') {
+            stem.push('-');
+        }
+    }
+    while stem.ends_with('-') {
+        stem.pop();
+    }
+    stem
+}
+
+/// Find a file name for the problem's solution that is not used by another
+/// problem, like `a-watermelon-2` if `a-watermelon` has the same name but a
+/// different URL. Solutions without a recorded URL may be reused.
+fn free_file_stem(repo: &Repository, problem: &Problem) -> Result<String> {
+    let base = file_stem(&problem.name);
+    if base.is_empty() {
+        bail!("cannot name a file after the problem {:?}", problem.name);
+    }
+    let mut stem = base.clone();
+    for n in 2.. {
+        let config = read_table(&repo.test_path().join(&stem).join("tests.toml"))?;
+        match config.get("url").and_then(Value::as_str) {
+            Some(url) if url != problem.url => stem = format!("{base}-{n}"),
+            _ => break,
+        }
+    }
+    Ok(stem)
+}
+
+/// Set up a solution for the problem. The source file is copied from the
+/// repository's template, unless it already exists. The samples are saved as
+/// the test cases `sample-1`, `sample-2`, etc., and the URL and limits are
+/// stored in the problem's `tests.toml` file. Returns the solution, and
+/// whether its source file was created.
+pub fn create_problem<'a>(repo: &'a Repository, problem: &Problem) -> Result<(Program<'a>, bool)> {
+    let stem = free_file_stem(repo, problem)?;
+
+    // Create the source file
+    let template = repo.config().template.as_ref().map(|t| repo.root().join(t));
+    let extension = template
+        .as_ref()
+        .and_then(|t| t.extension())
+        .and_then(|e| e.to_str())
+        .unwrap_or("cpp");
+    let path = repo.source_path().join(format!("{stem}.{extension}"));
+    let created = !path.exists();
+    if created {
+        let contents = match &template {
+            Some(template) => fs::read(template)
+                .with_context(|| format!("failed to read template {:?}", template))?,
+            None => vec![],
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create dir {:?}", parent))?;
+        }
+        fs::write(&path, contents).with_context(|| format!("failed to write file {:?}", path))?;
+    }
+    let prog = repo.get_program(&path)?;
+
+    // Save the samples and the limits
+    for (i, sample) in problem.tests.iter().enumerate() {
+        let case = format!("sample-{}", i + 1);
+        write_test_file(&test_file_path(&prog, &case, "in"), sample.input.as_bytes())?;
+        write_test_file(
+            &test_file_path(&prog, &case, "out"),
+            sample.output.as_bytes(),
+        )?;
+    }
+    let config_path = prog.test_path().join("tests.toml");
+    let mut config = read_table(&config_path)?;
+    config.insert("url".into(), Value::String(problem.url.clone()));
+    if let Some(time_limit) = problem.time_limit {
+        config.insert("soft_timeout".into(), Value::Integer(time_limit as i64));
+        // Leave room to see by how much a slow solution exceeds the limit
+        config.insert("hard_timeout".into(), Value::Integer(2 * time_limit as i64));
+    }
+    if let Some(memory_limit) = problem.memory_limit {
+        config.insert("memory_limit".into(), Value::Integer(memory_limit as i64));
+    }
+    let cases: Vec<_> = (1..=problem.tests.len())
+        .map(|i| format!("sample-{i}"))
+        .collect();
+    mark_samples(&mut config, &cases)?;
+    write_table(&config_path, config)?;
+
+    // Read the new settings
+    let prog = repo.get_program(&path)?;
+    Ok((prog, created))
+}
+
+/// Read a TOML file as a table. Returns an empty table if the file does not
+/// exist.
+fn read_table(path: &Path) -> Result<Table> {
+    match fs::read_to_string(path) {
+        Ok(s) => toml::from_str(&s).with_context(|| format!("failed to parse {:?}", path)),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Table::new()),
+        Err(e) => Err(e).with_context(|| format!("failed to read {:?}", path)),
+    }
+}
+
+/// Write a table to a TOML file.
+fn write_table(path: &Path, table: Table) -> Result<()> {
+    // Serialized as a value, so that plain keys come before the tables
+    let contents = toml::to_string(&Value::Table(table))
+        .with_context(|| format!("failed to serialize {:?}", path))?;
+    write_test_file(path, contents.as_bytes())
+}
+
+/// Mark the test cases of the program as samples in its `tests.toml` file.
+pub(super) fn mark_sample_cases(prog: &Program, cases: &[String]) -> Result<()> {
+    let config_path = prog.test_path().join("tests.toml");
+    let mut config = read_table(&config_path)?;
+    mark_samples(&mut config, cases)?;
+    write_table(&config_path, config)
+}
+
+/// Add the sample cases to the `cases` array of the problem settings, and
+/// mark them as samples.
+fn mark_samples(config: &mut Table, names: &[String]) -> Result<()> {
+    let cases = config
+        .entry("cases")
+        .or_insert_with(|| Value::Array(vec![]))
+        .as_array_mut()
+        .context("`cases` in tests.toml is not an array")?;
+    for name in names {
+        let existing = cases
+            .iter_mut()
+            .filter_map(Value::as_table_mut)
+            .find(|c| c.get("name").and_then(Value::as_str) == Some(name));
+        match existing {
+            Some(case) => {
+                case.insert("sample".into(), Value::Boolean(true));
+            }
+            None => {
+                let mut case = Table::new();
+                case.insert("name".into(), Value::String(name.clone()));
+                case.insert("sample".into(), Value::Boolean(true));
+                cases.push(Value::Table(case));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::process;
+    use std::thread;
+
+    use super::*;
+    use crate::Config;
+
+    /// A payload like the ones that Competitive Companion sends, with the
+    /// fields that coman does not use.
+    const PAYLOAD: &str = r#"{
+        "name": "A. Watermelon",
+        "group": "Codeforces - Codeforces Beta Round #4 (Div. 2 Only)",
+        "url": "https://codeforces.com/problemset/problem/4/A",
+        "interactive": false,
+        "memoryLimit": 64,
+        "timeLimit": 1000,
+        "tests": [{"input": "8\n", "output": "YES\n"}],
+        "testType": "single",
+        "input": {"type": "stdin"},
+        "output": {"type": "stdout"},
+        "languages": {"java": {"mainClass": "Main", "taskClass": "AWatermelon"}},
+        "batch": {"id": "c2a0d6d4", "size": 1}
+    }"#;
+
+    fn problem(name: &str, url: &str) -> Problem {
+        Problem {
+            name: name.to_string(),
+            url: url.to_string(),
+            time_limit: Some(2000),
+            memory_limit: None,
+            tests: vec![Sample {
+                input: "1\n".to_string(),
+                output: "2\n".to_string(),
+            }],
+        }
+    }
+
+    /// Make an empty repository with the default settings.
+    fn repository(name: &str) -> Repository {
+        let root = std::env::temp_dir().canonicalize().unwrap().join(format!(
+            "coman-listen-{}-{}",
+            process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        Repository::new(root, Config::default())
+    }
+
+    #[test]
+    fn parse_payload() {
+        let problem: Problem = serde_json::from_str(PAYLOAD).unwrap();
+        assert_eq!(problem.name, "A. Watermelon");
+        assert_eq!(problem.time_limit, Some(1000));
+        assert_eq!(problem.memory_limit, Some(64));
+        assert_eq!(problem.tests.len(), 1);
+        assert_eq!(problem.tests[0].output, "YES\n");
+    }
+
+    #[test]
+    fn file_stems() {
+        assert_eq!(file_stem("A. Watermelon"), "a-watermelon");
+        assert_eq!(file_stem("  C - Sum (Hard) "), "c-sum-hard");
+        assert_eq!(file_stem("Ünïcode 問題"), "ünïcode-問題");
+        assert_eq!(file_stem("..."), "");
+    }
+
+    #[test]
+    fn receive_posted_payload() {
+        let listener = Listener::bind(0).unwrap();
+        let port = listener.server.server_addr().to_ip().unwrap().port();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            write!(
+                stream,
+                "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                PAYLOAD.len(),
+                PAYLOAD
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        let problem = listener.receive().unwrap();
+        assert_eq!(problem.url, "https://codeforces.com/problemset/problem/4/A");
+        assert!(client.join().unwrap().starts_with("HTTP/1.1 200"));
+    }
+
+    #[test]
+    fn create_problem_writes_samples_and_limits() {
+        let repo = repository("create");
+        let (prog, created) = create_problem(&repo, &problem("A. Sum", "u1")).unwrap();
+        assert!(created);
+        assert_eq!(prog.name(), "a-sum.cpp");
+        assert_eq!(
+            fs::read_to_string(prog.test_path().join("sample-1.out")).unwrap(),
+            "2\n"
+        );
+        let config = prog.problem_config();
+        assert_eq!(config.url.as_deref(), Some("u1"));
+        assert_eq!(config.soft_timeout, Some(2000));
+        assert_eq!(config.hard_timeout, Some(4000));
+        assert!(config.cases[0].sample);
+
+        // Receiving the same problem again reuses its solution
+        let (prog, created) = create_problem(&repo, &problem("A. Sum", "u1")).unwrap();
+        assert!(!created);
+        assert_eq!(prog.name(), "a-sum.cpp");
+        assert_eq!(prog.problem_config().cases.len(), 1);
+        fs::remove_dir_all(repo.root()).unwrap();
+    }
+
+    #[test]
+    fn create_problem_with_same_name() {
+        let repo = repository("same-name");
+        create_problem(&repo, &problem("A. Sum", "u1")).unwrap();
+        let (prog, created) = create_problem(&repo, &problem("A. Sum", "u2")).unwrap();
+        assert!(created);
+        assert_eq!(prog.name(), "a-sum-2.cpp");
+        assert_eq!(prog.problem_config().url.as_deref(), Some("u2"));
+        let first = repo
+            .get_program(repo.source_path().join("a-sum.cpp"))
+            .unwrap();
+        assert_eq!(first.problem_config().url.as_deref(), Some("u1"));
+        fs::remove_dir_all(repo.root()).unwrap();
+    }
+}
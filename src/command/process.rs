@@ -0,0 +1,324 @@
+use std::io;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Once;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Resources used by a child process.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// Peak resident set size in bytes, or 0 if unknown.
+    pub peak_memory: u64,
+    /// CPU time spent in user mode.
+    pub user_time: Duration,
+    /// CPU time spent in the kernel on behalf of the process.
+    pub system_time: Duration,
+}
+
+impl ResourceUsage {
+    /// Get the total CPU time used by the process.
+    pub fn cpu_time(&self) -> Duration {
+        self.user_time + self.system_time
+    }
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type Resource = libc::c_int;
+
+/// Set a resource limit in the command's process before it starts.
+fn set_rlimit(cmd: &mut Command, resource: Resource, soft: u64, hard: u64) {
+    let limit = libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    // SAFETY: setrlimit is async-signal-safe.
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setrlimit(resource, &limit) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Limit the data segment of the command's process to the given number of
+/// bytes. Allocations beyond the limit will fail.
+///
+/// On Linux, this covers the private writable memory of the process. Unlike
+/// the address space, it does not include memory that is only reserved,
+/// such as the heap that the JVM reserves when it starts.
+pub fn limit_memory(cmd: &mut Command, bytes: u64) {
+    set_rlimit(cmd, libc::RLIMIT_DATA, bytes, bytes);
+}
+
+/// Limit the CPU time of the command's process to the given number of
+/// seconds. The process receives `SIGXCPU` when it reaches the limit, and is
+/// killed one second later.
+pub fn limit_cpu_time(cmd: &mut Command, seconds: u64) {
+    set_rlimit(cmd, libc::RLIMIT_CPU, seconds, seconds + 1);
+}
+
+fn timeval_to_duration(tv: libc::timeval) -> Duration {
+    Duration::from_secs(tv.tv_sec.max(0) as u64) + Duration::from_micros(tv.tv_usec.max(0) as u64)
+}
+
+fn wait4(pid: u32, options: libc::c_int) -> io::Result<Option<(ExitStatus, ResourceUsage)>> {
+    let mut status = 0;
+    // SAFETY: rusage is a plain C struct, for which all zeroes is valid.
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: the pointers are valid for the duration of the call.
+        let ret = unsafe { libc::wait4(pid as libc::pid_t, &mut status, options, &mut rusage) };
+        if ret == 0 {
+            return Ok(None);
+        } else if ret > 0 {
+            break;
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+    // ru_maxrss is in bytes on Apple platforms, and in kilobytes elsewhere
+    let maxrss_unit = if cfg!(any(target_os = "macos", target_os = "ios")) {
+        1
+    } else {
+        1024
+    };
+    let usage = ResourceUsage {
+        peak_memory: rusage.ru_maxrss.max(0) as u64 * maxrss_unit,
+        user_time: timeval_to_duration(rusage.ru_utime),
+        system_time: timeval_to_duration(rusage.ru_stime),
+    };
+    Ok(Some((ExitStatus::from_raw(status), usage)))
+}
+
+/// How a child process exited.
+#[derive(Clone, Copy, Debug)]
+pub struct Exit {
+    pub status: ExitStatus,
+    pub usage: ResourceUsage,
+    /// The moment at which the process was seen to exit.
+    pub time: Instant,
+}
+
+/// Waits for a child process to exit in a background thread, so that the
+/// wait can be given a deadline.
+///
+/// Once the process has exited, it is reaped by the waiter, so it must not be
+/// waited for through its `Child` again.
+pub struct Waiter {
+    recv: Receiver<io::Result<Exit>>,
+}
+
+impl Waiter {
+    /// Start waiting for the child.
+    pub fn new(child: &Child) -> Waiter {
+        let pid = child.id();
+        let (send, recv) = mpsc::channel();
+        thread::spawn(move || {
+            let result = wait4(pid, 0).map(|exit| {
+                let (status, usage) = exit.unwrap();
+                Exit {
+                    status,
+                    usage,
+                    time: Instant::now(),
+                }
+            });
+            let _ = send.send(result);
+        });
+        Waiter { recv }
+    }
+
+    /// Wait for the child to exit, for up to the given amount of time.
+    /// Returns `None` if the child is still running.
+    pub fn wait_timeout(&self, timeout: Duration) -> io::Result<Option<Exit>> {
+        match self.recv.recv_timeout(timeout) {
+            Ok(result) => result.map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => panic!("waiter thread exited without a result"),
+        }
+    }
+
+    /// Wait for the child to exit.
+    pub fn wait(&self) -> io::Result<Exit> {
+        self.recv
+            .recv()
+            .expect("waiter thread exited without a result")
+    }
+}
+
+/// Time that processes are given to exit after `SIGTERM`, before they are
+/// killed with `SIGKILL`.
+pub const KILL_GRACE: Duration = Duration::from_millis(200);
+
+/// Process groups that are running, so that they can be killed if coman is
+/// interrupted. Empty slots are 0, and reserved slots are -1. There is room
+/// for far more groups than there are jobs on most machines; when the table
+/// is full, starting another group waits for a slot to be freed.
+static RUNNING_GROUPS: [AtomicI32; 1024] = [const { AtomicI32::new(0) }; 1024];
+const RESERVED: libc::pid_t = -1;
+
+extern "C" fn kill_running_groups(sig: libc::c_int) {
+    for slot in &RUNNING_GROUPS {
+        let pgid = slot.load(Ordering::SeqCst);
+        if pgid > 0 {
+            // SAFETY: kill is async-signal-safe.
+            unsafe { libc::kill(-pgid, libc::SIGKILL) };
+        }
+    }
+    // Die from the signal as if it had not been handled
+    // SAFETY: signal and raise are async-signal-safe.
+    unsafe {
+        libc::signal(sig, libc::SIG_DFL);
+        libc::raise(sig);
+    }
+}
+
+/// Kill the running process groups when coman is interrupted or terminated,
+/// since they are not in coman's process group and do not get the signal.
+fn install_signal_handlers() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        for sig in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            let handler = kill_running_groups as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // SAFETY: the handler only calls async-signal-safe functions.
+            unsafe {
+                if libc::signal(sig, handler) == libc::SIG_IGN {
+                    // Keep ignoring signals that coman was told to ignore,
+                    // like SIGHUP under nohup
+                    libc::signal(sig, libc::SIG_IGN);
+                }
+            }
+        }
+    });
+}
+
+/// A slot in the table of running process groups, reserved for a group
+/// that is about to start. It is freed when this is dropped, unless the
+/// group was registered in it.
+pub struct GroupSlot {
+    index: usize,
+}
+
+impl GroupSlot {
+    /// Reserve a free slot, waiting for one if the table is full.
+    fn reserve() -> GroupSlot {
+        loop {
+            let index = RUNNING_GROUPS.iter().position(|slot| {
+                slot.compare_exchange(0, RESERVED, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            });
+            match index {
+                Some(index) => return GroupSlot { index },
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+}
+
+impl Drop for GroupSlot {
+    fn drop(&mut self) {
+        RUNNING_GROUPS[self.index].store(0, Ordering::SeqCst);
+    }
+}
+
+/// The process group of a child process, which contains the child and every
+/// process it starts. The whole group is killed when this is dropped, so
+/// that no process outlives the test it belongs to.
+pub struct ProcessGroup {
+    pgid: libc::pid_t,
+    /// Slot in which the group is registered, to be killed if coman is
+    /// interrupted. It is freed after the group is killed.
+    _slot: GroupSlot,
+}
+
+impl ProcessGroup {
+    /// Make the command's process the leader of a new session, and so of a
+    /// new process group. This also detaches it from the terminal. Returns
+    /// the slot that the group is registered in once it starts.
+    pub fn isolate(cmd: &mut Command) -> GroupSlot {
+        install_signal_handlers();
+        // SAFETY: setsid is async-signal-safe.
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        GroupSlot::reserve()
+    }
+
+    /// Take charge of the process group of a child whose command was
+    /// isolated with [`ProcessGroup::isolate`].
+    pub fn new(child: &Child, slot: GroupSlot) -> ProcessGroup {
+        let pgid = child.id() as libc::pid_t;
+        RUNNING_GROUPS[slot.index].store(pgid, Ordering::SeqCst);
+        ProcessGroup { pgid, _slot: slot }
+    }
+
+    /// Get a handle for signalling the group from another thread.
+    pub fn handle(&self) -> GroupHandle {
+        GroupHandle { pgid: self.pgid }
+    }
+
+    /// Send a signal to every process in the group.
+    pub fn signal(&self, sig: libc::c_int) {
+        self.handle().signal(sig);
+    }
+
+    /// Terminate the group, whose leader is still running. The group is sent
+    /// `SIGTERM`, and then `SIGKILL` once the leader has exited or the grace
+    /// period is over. Returns how the leader exited.
+    pub fn terminate(&self, waiter: &Waiter) -> io::Result<Exit> {
+        self.signal(libc::SIGTERM);
+        let exit = match waiter.wait_timeout(KILL_GRACE)? {
+            Some(exit) => exit,
+            None => {
+                self.signal(libc::SIGKILL);
+                waiter.wait()?
+            }
+        };
+        // Other processes in the group may have ignored SIGTERM
+        self.signal(libc::SIGKILL);
+        Ok(exit)
+    }
+
+    /// Terminate the processes left in the group after its leader exited.
+    /// They are sent `SIGTERM`, and then `SIGKILL` after the grace period.
+    pub fn terminate_remaining(&self) {
+        self.signal(libc::SIGTERM);
+        thread::sleep(KILL_GRACE);
+        self.signal(libc::SIGKILL);
+    }
+}
+
+/// A handle for signalling a process group. It should not outlive the
+/// `ProcessGroup`, since the group's ID may be reused once it is gone.
+#[derive(Clone, Copy, Debug)]
+pub struct GroupHandle {
+    pgid: libc::pid_t,
+}
+
+impl GroupHandle {
+    /// Send a signal to every process in the group.
+    pub fn signal(&self, sig: libc::c_int) {
+        // The group may be gone already, so errors are ignored
+        // SAFETY: kill has no memory safety requirements.
+        unsafe { libc::kill(-self.pgid, sig) };
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        self.signal(libc::SIGKILL);
+    }
+}
@@ -2,6 +2,7 @@ use std::process::Command;
 
 use anyhow::{Context, Result};
 
+use crate::command::supervise::Limits;
 use crate::Program;
 
 use super::{eval_command_template, RunResult};
@@ -20,10 +21,13 @@ pub fn get_run_command(prog: &Program) -> Command {
 
 /// Run the program in release mode. Returns true if the program
 /// exited with success, otherwise returns false. The program's
-/// stdin, stdout, and stderr are all inherited.
+/// stdin, stdout, and stderr are all inherited. The program runs in the
+/// sandbox if it is enabled, but without the other limits of a test.
 pub fn run(prog: &Program, args: &[&str]) -> Result<RunResult> {
     let mut cmd = get_run_command(prog);
     cmd.args(args);
+    let limits = Limits::for_program(prog);
+    let _scratch = limits.sandbox.map(|s| s.apply(&mut cmd)).transpose()?;
     let stat = cmd
         .status()
         .with_context(|| format!("failed to run command {:?}", cmd))?;
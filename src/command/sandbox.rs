@@ -0,0 +1,407 @@
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, Context, Result};
+
+use crate::SandboxConfig;
+
+/// Audit architecture of the system calls that the seccomp filter allows.
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// System calls that kill the program when the seccomp filter is enabled:
+/// networking, debugging other processes, and changing the system or the
+/// sandbox itself.
+const FORBIDDEN_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_socket,
+    libc::SYS_connect,
+    libc::SYS_bind,
+    libc::SYS_listen,
+    libc::SYS_accept,
+    libc::SYS_accept4,
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_mount_setattr,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_userfaultfd,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_kexec_load,
+    libc::SYS_reboot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+];
+
+/// `MOUNT_ATTR_RDONLY` from `linux/mount.h`.
+const MOUNT_ATTR_RDONLY: u64 = 0x1;
+
+/// `struct mount_attr` from `linux/mount.h`.
+#[repr(C)]
+struct MountAttr {
+    attr_set: u64,
+    attr_clr: u64,
+    propagation: u64,
+    userns_fd: u64,
+}
+
+/// Number of scratch directories made by this process so far.
+static SCRATCH_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The sandbox that programs run in, if it is enabled.
+#[derive(Clone, Debug)]
+pub struct Sandbox {
+    config: SandboxConfig,
+    /// Directory in which the scratch directories are made.
+    scratch_root: PathBuf,
+}
+
+/// A program's scratch directory, which is deleted when this is dropped.
+pub struct Scratch {
+    path: PathBuf,
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+impl Sandbox {
+    /// Get the sandbox described by the configuration, or `None` if it is
+    /// disabled. Scratch directories are made in the build directory.
+    pub fn new(config: &SandboxConfig, build_dir: &Path) -> Option<Sandbox> {
+        if !config.enabled {
+            return None;
+        }
+        Some(Sandbox {
+            config: config.clone(),
+            scratch_root: build_dir.join("sandbox"),
+        })
+    }
+
+    /// Whether programs that make forbidden system calls are killed.
+    pub fn uses_seccomp(&self) -> bool {
+        self.config.seccomp
+    }
+
+    /// Make the command run in the sandbox, in a new scratch directory. The
+    /// directory lasts as long as the returned `Scratch`.
+    pub fn apply(&self, cmd: &mut Command) -> Result<Scratch> {
+        let n = SCRATCH_COUNT.fetch_add(1, Ordering::SeqCst);
+        let path = self.scratch_root.join(format!("{}-{}", process::id(), n));
+        fs::create_dir_all(&path).with_context(|| format!("failed to create dir {:?}", path))?;
+        let scratch = Scratch { path };
+
+        let filter = if self.config.seccomp {
+            match AUDIT_ARCH {
+                Some(arch) => Some(seccomp_filter(arch)),
+                None => bail!("seccomp filters are not supported on this architecture"),
+            }
+        } else {
+            None
+        };
+
+        // Everything is prepared here, since the child may not allocate
+        // memory before it executes the program
+        let scratch_path = CString::new(scratch.path.as_os_str().as_bytes())
+            .context("scratch dir path contains a null byte")?;
+        // SAFETY: getuid and getgid cannot fail.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        // The program must not be root inside the sandbox, or it could undo
+        // the read-only view
+        let uid_map = format!("{} {} 1", if uid == 0 { 1000 } else { uid }, uid);
+        let gid_map = format!("{} {} 1", if gid == 0 { 1000 } else { gid }, gid);
+        let max_processes = self.config.max_processes;
+
+        cmd.env("TMPDIR", &scratch.path);
+        // SAFETY: the closure only makes async-signal-safe system calls.
+        unsafe {
+            cmd.pre_exec(move || {
+                enter_sandbox(
+                    &scratch_path,
+                    uid_map.as_bytes(),
+                    gid_map.as_bytes(),
+                    max_processes,
+                    filter.as_deref(),
+                )
+            });
+        }
+        Ok(scratch)
+    }
+}
+
+fn check(ret: libc::c_long) -> io::Result<()> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Write the data to a file under `/proc`.
+fn write_proc_file(path: &CStr, data: &[u8]) -> io::Result<()> {
+    // SAFETY: the pointers are valid for the duration of the calls.
+    unsafe {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        check(fd as libc::c_long)?;
+        let ret = libc::write(fd, data.as_ptr().cast(), data.len());
+        libc::close(fd);
+        check(ret as libc::c_long)
+    }
+}
+
+/// Set the mount attributes of the mount at the path, and of the mounts
+/// below it if `recursive` is set.
+fn mount_setattr(path: &CStr, recursive: bool, attr: &MountAttr) -> io::Result<()> {
+    let flags = if recursive { libc::AT_RECURSIVE } else { 0 };
+    // SAFETY: the pointers are valid for the duration of the call.
+    check(unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            flags,
+            attr as *const MountAttr,
+            std::mem::size_of::<MountAttr>(),
+        )
+    })
+}
+
+/// Isolate the current process, in the child before it executes the program.
+/// It gets new user, mount and network namespaces, in which every mount is
+/// read-only except the scratch directory, and there is no network.
+fn enter_sandbox(
+    scratch: &CStr,
+    uid_map: &[u8],
+    gid_map: &[u8],
+    max_processes: u64,
+    filter: Option<&[libc::sock_filter]>,
+) -> io::Result<()> {
+    let root = CStr::from_bytes_with_nul(b"/\0").unwrap();
+    let setgroups = CStr::from_bytes_with_nul(b"/proc/self/setgroups\0").unwrap();
+    let uid_map_path = CStr::from_bytes_with_nul(b"/proc/self/uid_map\0").unwrap();
+    let gid_map_path = CStr::from_bytes_with_nul(b"/proc/self/gid_map\0").unwrap();
+    let read_only = MountAttr {
+        attr_set: MOUNT_ATTR_RDONLY,
+        attr_clr: 0,
+        propagation: 0,
+        userns_fd: 0,
+    };
+    let writable = MountAttr {
+        attr_set: 0,
+        attr_clr: MOUNT_ATTR_RDONLY,
+        propagation: 0,
+        userns_fd: 0,
+    };
+
+    // SAFETY: the pointers are valid for the duration of the calls.
+    unsafe {
+        check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWNET) as _)?;
+        write_proc_file(setgroups, b"deny")?;
+        write_proc_file(uid_map_path, uid_map)?;
+        write_proc_file(gid_map_path, gid_map)?;
+
+        // Keep the changes to the mounts from reaching the rest of the system
+        check(libc::mount(
+            std::ptr::null(),
+            root.as_ptr(),
+            std::ptr::null(),
+            libc::MS_REC | libc::MS_PRIVATE,
+            std::ptr::null(),
+        ) as _)?;
+        check(libc::mount(
+            scratch.as_ptr(),
+            scratch.as_ptr(),
+            std::ptr::null(),
+            libc::MS_BIND,
+            std::ptr::null(),
+        ) as _)?;
+        mount_setattr(root, true, &read_only)?;
+        mount_setattr(scratch, false, &writable)?;
+        check(libc::chdir(scratch.as_ptr()) as _)?;
+
+        if max_processes > 0 {
+            let limit = libc::rlimit {
+                rlim_cur: max_processes as libc::rlim_t,
+                rlim_max: max_processes as libc::rlim_t,
+            };
+            check(libc::setrlimit(libc::RLIMIT_NPROC, &limit) as _)?;
+        }
+
+        check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) as _)?;
+        if let Some(filter) = filter {
+            let prog = libc::sock_fprog {
+                len: filter.len() as libc::c_ushort,
+                filter: filter.as_ptr() as *mut libc::sock_filter,
+            };
+            check(libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &prog as *const libc::sock_fprog,
+            ) as _)?;
+        }
+    }
+    Ok(())
+}
+
+fn bpf_stmt(code: u32, k: u32) -> libc::sock_filter {
+    bpf_jump(code, k, 0, 0)
+}
+
+fn bpf_jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// Build a seccomp filter that kills the process when it makes one of the
+/// forbidden system calls, or a system call of another architecture.
+fn seccomp_filter(arch: u32) -> Vec<libc::sock_filter> {
+    // Offsets of the fields of `struct seccomp_data`
+    const NR: u32 = 0;
+    const ARCH: u32 = 4;
+    let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+    let jeq = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+    let ret = libc::BPF_RET | libc::BPF_K;
+
+    let mut filter = vec![
+        bpf_stmt(load, ARCH),
+        bpf_jump(jeq, arch, 1, 0),
+        bpf_stmt(ret, libc::SECCOMP_RET_KILL_PROCESS),
+        bpf_stmt(load, NR),
+    ];
+    if cfg!(target_arch = "x86_64") {
+        // The x32 ABI has its own system call numbers
+        let jge = libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K;
+        filter.push(bpf_jump(
+            jge,
+            0x4000_0000,
+            FORBIDDEN_SYSCALLS.len() as u8 + 1,
+            0,
+        ));
+    }
+    // Each check jumps past the rest of them and the allowing return to the
+    // killing one
+    let n = FORBIDDEN_SYSCALLS.len();
+    for (i, &nr) in FORBIDDEN_SYSCALLS.iter().enumerate() {
+        filter.push(bpf_jump(jeq, nr as u32, (n - i) as u8, 0));
+    }
+    filter.push(bpf_stmt(ret, libc::SECCOMP_RET_ALLOW));
+    filter.push(bpf_stmt(ret, libc::SECCOMP_RET_KILL_PROCESS));
+    filter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run a seccomp filter on a system call, returning its action.
+    fn run_filter(filter: &[libc::sock_filter], arch: u32, nr: u32) -> u32 {
+        let mut acc = 0;
+        let mut pc = 0;
+        loop {
+            let ins = filter[pc];
+            let code = ins.code as u32;
+            pc += 1;
+            if code == libc::BPF_LD | libc::BPF_W | libc::BPF_ABS {
+                acc = match ins.k {
+                    0 => nr,
+                    4 => arch,
+                    k => panic!("unexpected offset {}", k),
+                };
+            } else if code == libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K {
+                pc += if acc == ins.k { ins.jt } else { ins.jf } as usize;
+            } else if code == libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K {
+                pc += if acc >= ins.k { ins.jt } else { ins.jf } as usize;
+            } else if code == libc::BPF_RET | libc::BPF_K {
+                return ins.k;
+            } else {
+                panic!("unexpected instruction {:#x}", code);
+            }
+        }
+    }
+
+    #[test]
+    fn seccomp_filter_actions() {
+        let arch = AUDIT_ARCH.unwrap();
+        let filter = seccomp_filter(arch);
+        let allowed = [
+            libc::SYS_read,
+            libc::SYS_write,
+            libc::SYS_mmap,
+            libc::SYS_clone,
+            libc::SYS_exit_group,
+        ];
+        for nr in allowed {
+            assert_eq!(
+                run_filter(&filter, arch, nr as u32),
+                libc::SECCOMP_RET_ALLOW,
+                "syscall {}",
+                nr
+            );
+        }
+        for &nr in FORBIDDEN_SYSCALLS {
+            assert_eq!(
+                run_filter(&filter, arch, nr as u32),
+                libc::SECCOMP_RET_KILL_PROCESS,
+                "syscall {}",
+                nr
+            );
+        }
+    }
+
+    #[test]
+    fn seccomp_filter_other_abis() {
+        let arch = AUDIT_ARCH.unwrap();
+        let filter = seccomp_filter(arch);
+        // i386 system calls have other numbers
+        assert_eq!(
+            run_filter(&filter, 0x4000_0003, libc::SYS_read as u32),
+            libc::SECCOMP_RET_KILL_PROCESS
+        );
+        if cfg!(target_arch = "x86_64") {
+            assert_eq!(
+                run_filter(&filter, arch, 0x4000_0000 | libc::SYS_read as u32),
+                libc::SECCOMP_RET_KILL_PROCESS
+            );
+        }
+    }
+
+    #[test]
+    fn seccomp_filter_jumps_fit() {
+        // The jumps over the checks are stored in a byte
+        assert!(FORBIDDEN_SYSCALLS.len() < u8::MAX as usize);
+        let filter = seccomp_filter(AUDIT_ARCH.unwrap());
+        for (i, ins) in filter.iter().enumerate() {
+            // The class of the instruction is in its lowest three bits
+            if ins.code as u32 & 0x07 == libc::BPF_JMP {
+                assert!(i + 1 + (ins.jt.max(ins.jf) as usize) < filter.len());
+            }
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Result};
+
+use crate::SandboxConfig;
+
+/// The sandbox, which is only supported on Linux. Programs cannot be run
+/// while it is enabled.
+#[derive(Clone, Debug)]
+pub struct Sandbox;
+
+/// A program's scratch directory, which is never made.
+pub struct Scratch;
+
+impl Sandbox {
+    /// Get the sandbox described by the configuration, or `None` if it is
+    /// disabled.
+    pub fn new(config: &SandboxConfig, _build_dir: &Path) -> Option<Sandbox> {
+        if config.enabled {
+            Some(Sandbox)
+        } else {
+            None
+        }
+    }
+
+    /// Whether programs that make forbidden system calls are killed.
+    pub fn uses_seccomp(&self) -> bool {
+        false
+    }
+
+    /// Fail, since programs cannot be sandboxed on this platform.
+    pub fn apply(&self, _cmd: &mut Command) -> Result<Scratch> {
+        bail!("the sandbox is only supported on Linux; disable it in Coman.toml");
+    }
+}
@@ -0,0 +1,353 @@
+use std::io::Read;
+use std::mem::{self, Discriminant};
+
+use anyhow::{bail, Context, Result};
+
+use crate::command::{
+    judge, load_test_args, open_optional_test_file, open_test_file, run_reference, test_file_path,
+    write_test_file, TestResult, TestStatus,
+};
+use crate::Program;
+
+/// An input, split into lines of whitespace-separated tokens.
+type Input = Vec<Vec<String>>;
+
+/// Smallest failing input found by shrinking a test case.
+pub struct Shrunk {
+    /// Name of the test case the input was saved as.
+    pub case: String,
+    /// Size of the original input in bytes.
+    pub original_size: usize,
+    pub input: Vec<u8>,
+    pub result: TestResult,
+}
+
+fn parse(data: &[u8]) -> Input {
+    String::from_utf8_lossy(data)
+        .lines()
+        .map(|line| line.split_whitespace().map(str::to_string).collect())
+        .collect()
+}
+
+fn render(input: &Input) -> Vec<u8> {
+    let mut data = vec![];
+    for line in input {
+        data.extend_from_slice(line.join(" ").as_bytes());
+        data.push(b'\n');
+    }
+    data
+}
+
+/// Get smaller values to try in place of a number, from smallest to largest.
+fn smaller_numbers(n: i64) -> Vec<i64> {
+    let mut v = vec![0, n.signum(), n / 2, n - n.signum()];
+    v.retain(|&m| m.unsigned_abs() < n.unsigned_abs());
+    v.dedup();
+    v
+}
+
+struct Shrinker<'a, 'p> {
+    prog: &'a Program<'p>,
+    reference: Option<&'a Program<'p>>,
+    args: Vec<String>,
+    /// Expected output, used if there is no reference solution.
+    expected: Vec<u8>,
+    best: Input,
+    /// Kind of verdict that the original input got. Smaller inputs only
+    /// count as failing if they get the same kind, so that for example a
+    /// wrong answer does not turn into a crash on an invalid input.
+    kind: Option<Discriminant<TestStatus>>,
+    progress: &'a mut dyn FnMut(usize),
+}
+
+impl Shrinker<'_, '_> {
+    /// Test the solution on the input. Returns the failed test and the
+    /// expected output, or `None` if the failure does not reproduce with the
+    /// same kind of verdict. Inputs which the reference solution rejects are
+    /// considered not to fail.
+    fn fails(&self, input: &[u8]) -> Result<Option<(TestResult, Vec<u8>)>> {
+        let expected = match self.reference {
+            Some(reference) => match run_reference(reference, &self.args, input)? {
+                Ok(expected) => expected,
+                Err(_) => return Ok(None),
+            },
+            None => self.expected.clone(),
+        };
+        let result = judge(self.prog, "shrink", &self.args, input, &expected)?;
+        let kind = mem::discriminant(&result.status);
+        if result.passed() || self.kind.is_some_and(|k| k != kind) {
+            Ok(None)
+        } else {
+            Ok(Some((result, expected)))
+        }
+    }
+
+    /// Keep the candidate if the failure still reproduces with it.
+    fn try_candidate(&mut self, candidate: Input) -> Result<bool> {
+        let data = render(&candidate);
+        match self.fails(&data)? {
+            Some(_) => {
+                self.best = candidate;
+                (self.progress)(data.len());
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Try the candidate, which has `count` fewer items starting at `line`,
+    /// as is and with each number on the likely header lines lowered by
+    /// `count`. The count of items is usually on the first line, or the line
+    /// before them.
+    fn try_with_headers(&mut self, candidate: Input, line: usize, count: usize) -> Result<bool> {
+        if self.try_candidate(candidate.clone())? {
+            return Ok(true);
+        }
+        let mut headers = vec![0, line.saturating_sub(1)];
+        headers.dedup();
+        for h in headers {
+            if h >= line {
+                continue;
+            }
+            for t in 0..candidate[h].len() {
+                if let Ok(n) = candidate[h][t].parse::<i64>() {
+                    if n >= count as i64 {
+                        let mut lowered = candidate.clone();
+                        lowered[h][t] = (n - count as i64).to_string();
+                        if self.try_candidate(lowered)? {
+                            return Ok(true);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Remove chunks of lines, halving the chunk size down to single lines.
+    fn drop_lines(&mut self) -> Result<bool> {
+        let mut changed = false;
+        let mut chunk = (self.best.len() / 2).max(1);
+        loop {
+            let mut i = 0;
+            while i < self.best.len() {
+                let end = (i + chunk).min(self.best.len());
+                let mut candidate = self.best.clone();
+                candidate.drain(i..end);
+                if self.try_with_headers(candidate, i, end - i)? {
+                    changed = true;
+                } else {
+                    i += chunk;
+                }
+            }
+            if chunk == 1 {
+                return Ok(changed);
+            }
+            chunk /= 2;
+        }
+    }
+
+    /// Remove chunks of tokens from within each line.
+    fn drop_tokens(&mut self) -> Result<bool> {
+        let mut changed = false;
+        let mut line = 0;
+        while line < self.best.len() {
+            let mut chunk = (self.best[line].len() / 2).max(1);
+            loop {
+                let mut i = 0;
+                while line < self.best.len() && i < self.best[line].len() {
+                    let end = (i + chunk).min(self.best[line].len());
+                    let mut candidate = self.best.clone();
+                    candidate[line].drain(i..end);
+                    if self.try_with_headers(candidate, line, end - i)? {
+                        changed = true;
+                    } else {
+                        i += chunk;
+                    }
+                }
+                if chunk == 1 {
+                    break;
+                }
+                chunk /= 2;
+            }
+            line += 1;
+        }
+        Ok(changed)
+    }
+
+    /// Replace numbers with smaller ones.
+    fn shrink_numbers(&mut self) -> Result<bool> {
+        let mut changed = false;
+        let mut line = 0;
+        while line < self.best.len() {
+            let mut t = 0;
+            while t < self.best[line].len() {
+                let mut improved = true;
+                while improved {
+                    improved = false;
+                    let n = match self.best[line][t].parse::<i64>() {
+                        Ok(n) => n,
+                        Err(_) => break,
+                    };
+                    for m in smaller_numbers(n) {
+                        let mut candidate = self.best.clone();
+                        candidate[line][t] = m.to_string();
+                        if self.try_candidate(candidate)? {
+                            changed = true;
+                            improved = true;
+                            break;
+                        }
+                    }
+                }
+                t += 1;
+            }
+            line += 1;
+        }
+        Ok(changed)
+    }
+}
+
+/// Shrink a failing test case of the program, by removing lines and tokens
+/// and making numbers smaller while the program still fails. If a reference
+/// solution is given, the expected output of each smaller input comes from
+/// it. Otherwise, the problem's checker judges the output against the case's
+/// original expected output. The smallest input is saved as the test case
+/// `{case}-min`, next to the original. `progress` is called with the size of
+/// the input whenever a smaller one is found.
+pub fn shrink(
+    prog: &Program,
+    case: &str,
+    reference: Option<&Program>,
+    progress: &mut dyn FnMut(usize),
+) -> Result<Shrunk> {
+    if prog.interactor()?.is_some() {
+        bail!("cannot shrink tests of {} on an interactive problem", prog);
+    }
+    if reference.is_none() && prog.checker()?.is_none() {
+        bail!("a reference solution is needed to shrink tests of a problem without a checker");
+    }
+
+    let mut input = vec![];
+    open_test_file(prog, case, "in")?
+        .read_to_end(&mut input)
+        .context("failed to read input file")?;
+    let mut expected = vec![];
+    if let Some(mut f) = open_optional_test_file(prog, case, "out")? {
+        f.read_to_end(&mut expected)
+            .context("failed to read output file")?;
+    }
+
+    let mut shrinker = Shrinker {
+        prog,
+        reference,
+        args: load_test_args(prog, case)?,
+        expected,
+        best: parse(&input),
+        kind: None,
+        progress,
+    };
+    let original = match shrinker.fails(&input)? {
+        Some(failure) => failure,
+        None => bail!("test case {} does not fail", case),
+    };
+    shrinker.kind = Some(mem::discriminant(&original.0.status));
+
+    // Apply the reductions until none of them makes progress
+    loop {
+        let mut changed = shrinker.drop_lines()?;
+        changed |= shrinker.drop_tokens()?;
+        changed |= shrinker.shrink_numbers()?;
+        if !changed {
+            break;
+        }
+    }
+
+    // The input may have been normalized without getting any smaller
+    let mut best = render(&shrinker.best);
+    let (result, expected) = match shrinker.fails(&best)? {
+        Some(failure) => failure,
+        None => {
+            best = input.clone();
+            original
+        }
+    };
+
+    let min_case = format!("{case}-min");
+    for (ext, data) in [("in", &best), ("out", &expected)] {
+        write_test_file(&test_file_path(prog, &min_case, ext), data)?;
+    }
+
+    Ok(Shrunk {
+        case: min_case,
+        original_size: input.len(),
+        input: best,
+        result,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::process;
+
+    use super::*;
+    use crate::Repository;
+
+    #[test]
+    fn smaller_numbers_move_towards_zero() {
+        assert!(smaller_numbers(0).is_empty());
+        assert_eq!(smaller_numbers(1), vec![0]);
+        assert_eq!(smaller_numbers(-1), vec![0]);
+        assert_eq!(smaller_numbers(10), vec![0, 1, 5, 9]);
+        assert_eq!(smaller_numbers(-3), vec![0, -1, -2]);
+    }
+
+    #[test]
+    fn smaller_numbers_at_the_extremes() {
+        assert_eq!(
+            smaller_numbers(i64::MIN),
+            vec![0, -1, i64::MIN / 2, i64::MIN + 1]
+        );
+        assert_eq!(
+            smaller_numbers(i64::MAX),
+            vec![0, 1, i64::MAX / 2, i64::MAX - 1]
+        );
+    }
+
+    #[test]
+    fn wrong_answer_does_not_shrink_into_crash() {
+        let root = std::env::temp_dir()
+            .canonicalize()
+            .unwrap()
+            .join(format!("coman-shrink-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("test/p")).unwrap();
+        fs::write(
+            root.join("Coman.toml"),
+            "[languages.py]\nrun = [\"python3\", \"{source}\"]\n",
+        )
+        .unwrap();
+        // Wrong on three or more numbers, and crashes on fewer
+        fs::write(
+            root.join("src/p.py"),
+            "import sys\nassert len(sys.stdin.read().split()) >= 3\nprint(0)\n",
+        )
+        .unwrap();
+        fs::write(root.join("src/ref.py"), "print(1)\n").unwrap();
+        fs::write(root.join("test/p/1.in"), "5\n4 3\n2 1\n").unwrap();
+        fs::write(root.join("test/p/1.out"), "1\n").unwrap();
+
+        let repo = Repository::read(&root).unwrap();
+        let prog = repo.get_program(root.join("src/p.py")).unwrap();
+        let reference = repo.get_program(root.join("src/ref.py")).unwrap();
+        let shrunk = shrink(&prog, "1", Some(&reference), &mut |_| {}).unwrap();
+        let tokens = String::from_utf8(shrunk.input).unwrap();
+        assert_eq!(
+            tokens.split_whitespace().collect::<Vec<_>>(),
+            ["0", "0", "0"]
+        );
+        assert_eq!(shrunk.result.status, TestStatus::Wrong);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use std::io::{self, Cursor};
+
+use anyhow::{bail, Context, Result};
+
+use crate::command::supervise::{execute, Limits};
+use crate::command::{
+    check_dir, get_run_command, run_checker, run_verdict, write_check_file, TestResult, TestStatus,
+};
+use crate::Program;
+
+/// A generated test on which the solution failed.
+pub struct Counterexample {
+    /// Input produced by the generator.
+    pub input: Vec<u8>,
+    /// Output of the reference solution.
+    pub expected: Vec<u8>,
+    pub result: TestResult,
+}
+
+/// Run one round of stress testing. The generator is run with the seed as
+/// its only argument, and its output is given to both the solution and the
+/// reference solution. The solution's output is judged the same way as in
+/// a normal test, with the reference's output as the expected output.
+/// Returns the test if the solution failed it.
+pub fn stress(
+    prog: &Program,
+    generator: &Program,
+    reference: &Program,
+    seed: u64,
+) -> Result<Option<Counterexample>> {
+    if prog.interactor()?.is_some() {
+        bail!("cannot stress test {} on an interactive problem", prog);
+    }
+
+    // Generate the input
+    let limits = Limits::for_program(generator);
+    let mut cmd = get_run_command(generator);
+    cmd.arg(seed.to_string());
+    let gen = execute(&mut cmd, io::empty(), &limits)?;
+    if let Some(status) = run_verdict(&gen, &limits, None) {
+        bail!(
+            "generator {} failed with seed {}: {}",
+            generator,
+            seed,
+            status
+        );
+    }
+    let input = gen.stdout;
+
+    // Get the expected output
+    let expected = match run_reference(reference, &[], &input)? {
+        Ok(expected) => expected,
+        Err(status) => bail!(
+            "reference solution {} failed with seed {}: {}",
+            reference,
+            seed,
+            status,
+        ),
+    };
+
+    let result = judge(prog, "stress", &[], &input, &expected)?;
+    if result.passed() {
+        Ok(None)
+    } else {
+        Ok(Some(Counterexample {
+            input,
+            expected,
+            result,
+        }))
+    }
+}
+
+/// Run the reference solution on the input. Returns its output, or its
+/// verdict if it did not run successfully.
+pub(super) fn run_reference(
+    reference: &Program,
+    args: &[String],
+    input: &[u8],
+) -> Result<Result<Vec<u8>, TestStatus>> {
+    let limits = Limits::for_program(reference);
+    let mut cmd = get_run_command(reference);
+    cmd.args(args);
+    let execution = execute(&mut cmd, Cursor::new(input.to_vec()), &limits)?;
+    match run_verdict(&execution, &limits, None) {
+        Some(status) => Ok(Err(status)),
+        None => Ok(Ok(execution.stdout)),
+    }
+}
+
+/// Test the solution on an input that is not saved as a test case, judging
+/// its output the same way as in a normal test. If the problem has a
+/// checker, its files are written to the check directory under `name`.
+pub(super) fn judge(
+    prog: &Program,
+    name: &str,
+    args: &[String],
+    input: &[u8],
+    expected: &[u8],
+) -> Result<TestResult> {
+    let limits = Limits::for_program(prog);
+    let mut cmd = get_run_command(prog);
+    cmd.args(args);
+    let execution = execute(&mut cmd, Cursor::new(input.to_vec()), &limits)?;
+
+    let mut message = None;
+    let mut mismatch = None;
+    let status = if let Some(status) = run_verdict(&execution, &limits, None) {
+        status
+    } else if let Some(checker) = prog.checker()? {
+        let dir = check_dir(prog);
+        let in_path = dir.join(format!("{name}.in"));
+        let ans_path = dir.join(format!("{name}.ans"));
+        let out_path = dir.join(format!("{name}.actual"));
+        write_check_file(&in_path, &mut Cursor::new(input))?;
+        write_check_file(&ans_path, &mut Cursor::new(expected))?;
+        write_check_file(&out_path, &mut Cursor::new(&execution.stdout))?;
+        let (status, msg) = run_checker(&checker, &in_path, &out_path, &ans_path)
+            .with_context(|| format!("failed to check output with {}", checker))?;
+        message = Some(msg);
+        status
+    } else if prog.comparator().matches(&execution.stdout, expected) {
+        TestStatus::Pass
+    } else {
+        mismatch = Some(expected.to_vec());
+        TestStatus::Wrong
+    };
+
+    let mut result = TestResult::from_execution(execution, &limits, status, mismatch);
+    result.message = message;
+    Ok(result)
+}
@@ -0,0 +1,291 @@
+use std::io::{self, ErrorKind, Read};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::command::process::{
+    limit_cpu_time, limit_memory, GroupHandle, ProcessGroup, ResourceUsage, Waiter, KILL_GRACE,
+};
+use crate::command::sandbox::{Sandbox, Scratch};
+use crate::command::RunResult;
+use crate::{Program, Timing};
+
+/// Memory in bytes that a program may allocate beyond the memory limit
+/// before its allocations start to fail. The verdict is based on the peak
+/// resident memory, like most judges, so the operating system's limit only
+/// has to stop runaway allocations. It cannot be close to the memory limit,
+/// since runtimes such as the JVM reserve a large heap up front without
+/// using it. A program that crashes after using more than the memory limit
+/// still gets the memory limit verdict.
+const MEMORY_HEADROOM: u64 = 1024 * 1024 * 1024;
+
+/// Limits on the resources that a program may use during a test.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    pub soft_timeout: Duration,
+    pub hard_timeout: Duration,
+    pub timing: Timing,
+    /// Memory limit in bytes, or `None` if there is no limit.
+    pub memory: Option<u64>,
+    /// Limit on the size of each of stdout and stderr in bytes, or `None` if
+    /// there is no limit.
+    pub output: Option<u64>,
+    /// Sandbox to run the program in, if it is enabled.
+    pub sandbox: Option<Sandbox>,
+}
+
+impl Limits {
+    /// Get the limits configured for the program, taking the problem's
+    /// overrides into account.
+    pub fn for_program(prog: &Program) -> Limits {
+        let config = prog.repository().config();
+        let problem = prog.problem_config();
+        Limits {
+            soft_timeout: Duration::from_millis(
+                problem.soft_timeout.unwrap_or(config.soft_timeout),
+            ),
+            hard_timeout: Duration::from_millis(
+                problem.hard_timeout.unwrap_or(config.hard_timeout),
+            ),
+            timing: config.timing,
+            memory: match problem.memory_limit.unwrap_or(config.memory_limit) {
+                0 => None,
+                mib => Some(mib * 1024 * 1024),
+            },
+            output: match config.output_limit {
+                0 => None,
+                mib => Some(mib * 1024 * 1024),
+            },
+            sandbox: Sandbox::new(&config.sandbox, prog.repository().build_path()),
+        }
+    }
+
+    /// Get the limits for the program on a test case, taking the case's
+    /// overrides into account.
+    pub fn for_case(prog: &Program, case: &str) -> Limits {
+        let mut limits = Limits::for_program(prog);
+        if let Some(config) = prog.case_config(case) {
+            if let Some(soft_timeout) = config.soft_timeout {
+                limits.soft_timeout = Duration::from_millis(soft_timeout);
+            }
+            if let Some(hard_timeout) = config.hard_timeout {
+                limits.hard_timeout = Duration::from_millis(hard_timeout);
+            }
+        }
+        limits
+    }
+
+    /// Apply the limits enforced by the operating system to the command.
+    /// If the program is sandboxed, its scratch directory is returned, and
+    /// must be kept until the program exits.
+    pub fn apply(&self, cmd: &mut Command) -> Result<Option<Scratch>> {
+        if let Some(limit) = self.memory {
+            limit_memory(cmd, limit + MEMORY_HEADROOM);
+        }
+        if self.timing == Timing::Cpu {
+            let millis = self.hard_timeout.as_millis() as u64;
+            limit_cpu_time(cmd, millis.div_ceil(1000));
+        }
+        self.sandbox.as_ref().map(|s| s.apply(cmd)).transpose()
+    }
+
+    /// Get the real time after which the program is killed.
+    pub fn wall_deadline(&self) -> Duration {
+        match self.timing {
+            Timing::Wall => self.hard_timeout,
+            // Idle programs use no CPU time, so they need a separate limit
+            Timing::Cpu => self.hard_timeout * 2,
+        }
+    }
+
+    /// Get the time that counts against the timeouts.
+    pub fn counted_time(&self, wall_time: Duration, usage: &ResourceUsage) -> Duration {
+        match self.timing {
+            Timing::Wall => wall_time,
+            Timing::Cpu => usage.cpu_time(),
+        }
+    }
+}
+
+/// Outcome of running a program under supervision.
+#[derive(Clone, Debug)]
+pub struct Execution {
+    pub status: RunResult,
+    /// Whether the program was still running when it was killed at the
+    /// deadline.
+    pub killed: bool,
+    /// Real time between starting the program and its exit.
+    pub time: Duration,
+    pub usage: ResourceUsage,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Whether the program was killed for writing more than the output
+    /// limit to stdout or stderr. The captured output is cut off at the
+    /// limit.
+    pub output_exceeded: bool,
+}
+
+impl Execution {
+    /// Check whether the program ran past the hard timeout.
+    pub fn hard_timeout(&self, limits: &Limits) -> bool {
+        self.killed
+            || self.status == RunResult::Signal(libc::SIGXCPU)
+            || limits.counted_time(self.time, &self.usage) >= limits.hard_timeout
+    }
+
+    /// Check whether the program ran past the soft timeout.
+    pub fn soft_timeout(&self, limits: &Limits) -> bool {
+        limits.counted_time(self.time, &self.usage) >= limits.soft_timeout
+    }
+
+    /// Check whether the program was killed for making a forbidden system
+    /// call.
+    pub fn forbidden_syscall(&self, limits: &Limits) -> bool {
+        is_forbidden_syscall(&self.status, limits)
+    }
+
+    /// Check whether the program used more memory than the limit.
+    pub fn memory_exceeded(&self, limits: &Limits) -> bool {
+        limits
+            .memory
+            .is_some_and(|limit| self.usage.peak_memory > limit)
+    }
+}
+
+/// Check whether a program that exited with the status was killed by the
+/// sandbox's seccomp filter.
+pub fn is_forbidden_syscall(status: &RunResult, limits: &Limits) -> bool {
+    *status == RunResult::Signal(libc::SIGSYS)
+        && limits.sandbox.as_ref().is_some_and(Sandbox::uses_seccomp)
+}
+
+/// Read everything from the program's output stream, keeping at most `limit`
+/// bytes. If there is more, the program's process group is killed, so that
+/// it stops writing. Returns the data, and whether it was cut off.
+pub(super) fn capture(
+    mut from: impl Read,
+    limit: Option<u64>,
+    group: GroupHandle,
+) -> io::Result<(Vec<u8>, bool)> {
+    let mut buf = vec![];
+    let limit = match limit {
+        Some(limit) => limit,
+        None => {
+            from.read_to_end(&mut buf)?;
+            return Ok((buf, false));
+        }
+    };
+    from.take(limit + 1).read_to_end(&mut buf)?;
+    if buf.len() as u64 > limit {
+        group.signal(libc::SIGKILL);
+        buf.truncate(limit as usize);
+        Ok((buf, true))
+    } else {
+        Ok((buf, false))
+    }
+}
+
+/// Get the output captured by a thread, once the program has exited. The
+/// output may still be held open by processes that the program left running
+/// in the background, so they are terminated if it is not closed within a
+/// short grace period. This does not count against the program.
+fn collect_output(
+    recv: &Receiver<io::Result<(Vec<u8>, bool)>>,
+    group: &ProcessGroup,
+    name: &str,
+) -> Result<(Vec<u8>, bool)> {
+    let result = match recv.recv_timeout(KILL_GRACE) {
+        Ok(result) => result,
+        Err(_) => {
+            group.terminate_remaining();
+            match recv.recv_timeout(KILL_GRACE) {
+                Ok(result) => result,
+                // Held open by a process that left the group
+                Err(_) => return Ok((vec![], false)),
+            }
+        }
+    };
+    result.with_context(|| format!("error in {} capturing thread", name))
+}
+
+/// Run a command with the given input, capturing its output. The program is
+/// killed if it does not exit before the deadline of the limits, or if it
+/// writes more than the output limit.
+pub fn execute(
+    cmd: &mut Command,
+    mut input: impl Read + Send + 'static,
+    limits: &Limits,
+) -> Result<Execution> {
+    cmd.stdin(Stdio::piped());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    let _scratch = limits.apply(cmd)?;
+    let slot = ProcessGroup::isolate(cmd);
+    let begin = Instant::now();
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("failed to run command {:?}", cmd))?;
+    let group = ProcessGroup::new(&child, slot);
+    let waiter = Waiter::new(&child);
+
+    // Feed input file into stdin
+    let mut stdin = child.stdin.take().unwrap();
+    let in_thread = thread::spawn(move || match io::copy(&mut input, &mut stdin) {
+        // This thread copies the input data to the process's stdin.
+        Ok(_) => Ok(()),
+        Err(ref e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        Err(e) => Err(e),
+    });
+
+    // Capture the data from stdout and stderr. The data is sent through
+    // channels, so that we can stop waiting for it at the deadline.
+    let stdout = child.stdout.take().unwrap();
+    let (out_send, out_recv) = mpsc::channel();
+    let (output_limit, handle) = (limits.output, group.handle());
+    thread::spawn(move || {
+        let _ = out_send.send(capture(stdout, output_limit, handle));
+    });
+    let stderr = child.stderr.take().unwrap();
+    let (err_send, err_recv) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = err_send.send(capture(stderr, output_limit, handle));
+    });
+
+    // Wait for the process itself to exit, rather than for the end of its
+    // output, since it may close its output early
+    let mut killed = false;
+    let exit = match waiter.wait_timeout(limits.wall_deadline())? {
+        Some(exit) => exit,
+        None => {
+            // Program did not exit in time
+            killed = true;
+            group
+                .terminate(&waiter)
+                .context("failed to wait for child process")?
+        }
+    };
+
+    let (stdout, stdout_exceeded) = collect_output(&out_recv, &group, "stdout")?;
+    let (stderr, stderr_exceeded) = collect_output(&err_recv, &group, "stderr")?;
+    // Kill anything that the program left running in the background
+    drop(group);
+    if in_thread.is_finished() {
+        in_thread
+            .join()
+            .unwrap()
+            .context("error in input feeding thread")?;
+    }
+
+    Ok(Execution {
+        status: exit.status.into(),
+        killed,
+        time: exit.time - begin,
+        usage: exit.usage,
+        stdout,
+        stderr,
+        output_exceeded: stdout_exceeded || stderr_exceeded,
+    })
+}
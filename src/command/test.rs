@@ -1,77 +1,173 @@
+use std::ffi::OsString;
+use std::fmt;
 use std::fs::{self, File};
 use std::io::{self, Cursor, ErrorKind, Read};
-use std::path::Path;
-use std::process::Stdio;
-use std::sync::mpsc;
-use std::thread::{self, JoinHandle};
-use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{bail, Context, Result};
-use xz2::read::XzDecoder;
+use if_chain::if_chain;
+use walkdir::WalkDir;
 
-use crate::command::{get_run_command, RunResult};
-use crate::Program;
+use crate::command::compare::similarity;
+use crate::command::compress::{strip_compression_suffix, Compression, COMPRESSIONS, XZ};
+use crate::command::supervise::{execute, Execution, Limits};
+use crate::command::{get_run_command, test_interactive, RunResult};
+use crate::{Program, Timing};
+
+/// Size in bytes from which new test files are compressed, if compression is
+/// requested.
+pub const COMPRESS_THRESHOLD: usize = 64 * 1024;
 
 /// Get a list of the test cases. If the list of test cases cannot
 /// be accessed, then an empty vector is returned. Otherwise,
 /// returns a vector of the test case IDs.
 pub fn get_test_cases(prog: &Program) -> Result<Vec<String>> {
     let dir = prog.test_path();
-
-    let read = match fs::read_dir(dir) {
-        Ok(r) => r,
-        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
-        Err(e) => return Err(e).with_context(|| format!("failed to read dir {:?}", dir)),
-    };
+    let layout = prog.test_layout();
+    let (input_dir, depth) = layout.input_dir();
+    let input_dir = dir.join(input_dir);
+    if !input_dir.is_dir() {
+        return Ok(vec![]);
+    }
 
     let mut v = vec![];
-    for ent in read {
-        let ent = ent.with_context(|| format!("failed to read dir {:?}", dir))?;
-        if let Ok(mut s) = ent.file_name().into_string() {
-            if s.ends_with(".in") {
-                s.truncate(s.len() - ".in".len());
-                v.push(s);
-            } else if s.ends_with(".in.xz") {
-                s.truncate(s.len() - ".in.xz".len());
-                v.push(s);
+    let walk = WalkDir::new(&input_dir)
+        .min_depth(1)
+        .max_depth(depth)
+        .into_iter()
+        .filter_entry(|ent| {
+            !(ent.file_type().is_dir()
+                && ent
+                    .path()
+                    .strip_prefix(dir)
+                    .is_ok_and(|sub| is_other_program_test_dir(prog, sub)))
+        });
+    for ent in walk {
+        let ent = ent.with_context(|| format!("failed to read dir {:?}", input_dir))?;
+        if !ent.path().is_file() {
+            continue;
+        }
+        if_chain! {
+            if let Ok(path) = ent.path().strip_prefix(dir);
+            if let Some(path) = path.to_str();
+            let path = strip_compression_suffix(path);
+            if let Some(case) = layout.case_name(Path::new(path));
+            then {
+                v.push(case);
             }
         }
     }
     Ok(v)
 }
 
+/// Check whether a subdirectory of the program's test directory is the test
+/// directory of another program, like `c/d` for `c/d.cpp` next to `c.cpp`,
+/// rather than a test group.
+fn is_other_program_test_dir(prog: &Program, sub: &Path) -> bool {
+    let repo = prog.repository();
+    let src = match prog.test_path().strip_prefix(repo.test_path()) {
+        Ok(rel) => repo.source_path().join(rel).join(sub),
+        Err(_) => return false,
+    };
+    if src.is_dir() {
+        return true;
+    }
+    let (parent, name) = match (src.parent(), src.file_name()) {
+        (Some(parent), Some(name)) => (parent, name),
+        _ => return false,
+    };
+    fs::read_dir(parent)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .any(|ent| {
+            let mut stem = PathBuf::from(ent.file_name());
+            while stem.extension().is_some() {
+                stem.set_extension("");
+            }
+            stem.as_os_str() == name && ent.path().is_file()
+        })
+}
+
+/// Sort the test cases into the order in which they are run: first the cases
+/// listed in the problem's `tests.toml` file, in that order, then the other
+/// cases in alphanumeric order.
+pub fn sort_test_cases(prog: &Program, cases: &mut [String]) {
+    let listed = &prog.problem_config().cases;
+    alphanumeric_sort::sort_str_slice(cases);
+    cases.sort_by_key(|case| {
+        listed
+            .iter()
+            .position(|c| &c.name == case)
+            .unwrap_or(listed.len())
+    });
+}
+
+/// Get the path to a test file of the program, without any compression
+/// suffix. The extension is the one that the file has in the `Coman` layout.
+pub(super) fn test_file_path(prog: &Program, case: &str, extension: &str) -> PathBuf {
+    prog.test_path().join(
+        prog.test_layout()
+            .file_path(prog.test_path(), case, extension),
+    )
+}
+
 struct TestData {
     args: Vec<String>,
     in_file: Box<dyn Send + Read>,
-    out_file: Box<dyn Send + Read>,
+    /// Files of the accepted outputs, with their extensions.
+    out_files: Vec<(String, Box<dyn Send + Read>)>,
+    /// Exit code that the program must exit with, if not success.
+    code: Option<i32>,
+    /// File of the expected error stream, if it is checked.
+    err_file: Option<Box<dyn Send + Read>>,
 }
 
-fn open_optional_test_file(
+/// Find a test file of the program, which may be compressed. Returns the path
+/// of the file and its compression format, or `None` if the file does not
+/// exist.
+pub(super) fn find_test_file(
     prog: &Program,
     case: &str,
     extension: &str,
-) -> Result<Option<Box<dyn Read + Send>>> {
-    fn try_open(path: impl AsRef<Path>) -> Result<Option<File>> {
-        let path = path.as_ref();
-        match File::open(path) {
-            Ok(f) => Ok(Some(f)),
-            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(None),
-            Err(e) => Err(e).with_context(|| format!("failed to read file {:?}", path)),
+) -> Option<(PathBuf, Option<&'static Compression>)> {
+    // Try the compressed ones first
+    let path = test_file_path(prog, case, extension);
+    for compression in COMPRESSIONS {
+        let mut compressed_path = path.clone().into_os_string();
+        compressed_path.push(compression.suffix);
+        let compressed_path = PathBuf::from(compressed_path);
+        if compressed_path.is_file() {
+            return Some((compressed_path, Some(compression)));
         }
     }
+    if path.is_file() {
+        Some((path, None))
+    } else {
+        None
+    }
+}
 
-    // Try the xz-compressed one
-    let mut path = prog.test_path().join(format!("{case}.{extension}.xz"));
+/// Check whether the program has a test file, which may be compressed.
+pub fn has_test_file(prog: &Program, case: &str, extension: &str) -> bool {
+    find_test_file(prog, case, extension).is_some()
+}
 
-    let mut reader: Box<dyn Read + Send> = if let Some(file) = try_open(&path)? {
-        Box::new(XzDecoder::new(file))
-    } else {
-        path.set_extension("");
-        if let Some(file) = try_open(&path)? {
-            Box::new(file)
-        } else {
-            return Ok(None);
-        }
+pub(super) fn open_optional_test_file(
+    prog: &Program,
+    case: &str,
+    extension: &str,
+) -> Result<Option<Box<dyn Read + Send>>> {
+    let (path, compression) = match find_test_file(prog, case, extension) {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let file = File::open(&path).with_context(|| format!("failed to read file {:?}", path))?;
+    let mut reader: Box<dyn Read + Send> = match compression {
+        Some(compression) => (compression.decoder)(file)
+            .with_context(|| format!("failed to read file {:?}", path))?,
+        None => Box::new(file),
     };
 
     if prog.repository().config().buffering {
@@ -83,152 +179,525 @@ fn open_optional_test_file(
     }
 }
 
-fn open_test_file(prog: &Program, case: &str, extension: &str) -> Result<Box<dyn Read + Send>> {
+pub(super) fn open_test_file(
+    prog: &Program,
+    case: &str,
+    extension: &str,
+) -> Result<Box<dyn Read + Send>> {
     match open_optional_test_file(prog, case, extension)? {
         Some(f) => Ok(f),
         None => bail!("could not find '{}.{}' file for {}", case, extension, prog),
     }
 }
 
-/// Open the input and output files for the test case.
-fn load_test_data_for_case(prog: &Program, case: &str) -> Result<TestData> {
-    let args = match open_optional_test_file(prog, case, "args")? {
+/// Get the path to an uncompressed copy of a test file, or `None` if the
+/// test file does not exist. If the test file is compressed, it is
+/// decompressed into `dir` first.
+pub(super) fn uncompressed_optional_test_file(
+    prog: &Program,
+    case: &str,
+    extension: &str,
+    dir: &Path,
+) -> Result<Option<PathBuf>> {
+    let path = test_file_path(prog, case, extension);
+    if path.is_file() {
+        return Ok(Some(path));
+    }
+    match open_optional_test_file(prog, case, extension)? {
+        Some(mut reader) => {
+            let path = dir.join(format!("{case}.{extension}"));
+            write_check_file(&path, &mut reader)?;
+            Ok(Some(path))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Get the path to an uncompressed copy of a test file. If the test file is
+/// compressed, it is decompressed into `dir` first.
+pub(super) fn uncompressed_test_file(
+    prog: &Program,
+    case: &str,
+    extension: &str,
+    dir: &Path,
+) -> Result<PathBuf> {
+    match uncompressed_optional_test_file(prog, case, extension, dir)? {
+        Some(path) => Ok(path),
+        None => bail!("could not find '{}.{}' file for {}", case, extension, prog),
+    }
+}
+
+/// Write a test file, creating its parent directories.
+pub(super) fn write_test_file(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("failed to create dir {:?}", parent))?;
+    }
+    fs::write(path, data).with_context(|| format!("failed to write file {:?}", path))
+}
+
+/// Write a test file with the given compression, creating its parent
+/// directories. The path must already have the compression's suffix.
+pub(super) fn write_compressed_test_file(
+    path: &Path,
+    data: &[u8],
+    compression: Option<&Compression>,
+) -> Result<()> {
+    let compression = match compression {
+        Some(compression) => compression,
+        None => return write_test_file(path, data),
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("failed to create dir {:?}", parent))?;
+    }
+    let file = File::create(path).with_context(|| format!("failed to create file {:?}", path))?;
+    (compression.encode)(file, data).with_context(|| format!("failed to write file {:?}", path))
+}
+
+/// Write data to a file used by the checker, creating its parent directories.
+pub(super) fn write_check_file(path: &Path, data: &mut impl Read) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("failed to create dir {:?}", parent))?;
+    }
+    let mut file =
+        File::create(path).with_context(|| format!("failed to create file {:?}", path))?;
+    io::copy(data, &mut file).with_context(|| format!("failed to write file {:?}", path))?;
+    Ok(())
+}
+
+/// Run the special judge on the program's output. The checker is called with
+/// testlib-style arguments: the input file, the program's output, and the
+/// expected answer. Returns the verdict and the checker's message. The
+/// checker runs in its own process group, and fails if it does not finish
+/// within the hard timeout.
+pub(super) fn run_checker(
+    checker: &Program,
+    in_path: &Path,
+    out_path: &Path,
+    ans_path: &Path,
+) -> Result<(TestStatus, String)> {
+    let mut cmd = get_run_command(checker);
+    cmd.arg(in_path).arg(out_path).arg(ans_path);
+    // The checker is trusted, so only the time is limited
+    let limits = Limits {
+        timing: Timing::Wall,
+        memory: None,
+        output: None,
+        sandbox: None,
+        ..Limits::for_program(checker)
+    };
+    let result = execute(&mut cmd, io::empty(), &limits)
+        .with_context(|| format!("failed to run checker {}", checker))?;
+    if result.killed {
+        let message = format!("timed out after {:?}", limits.hard_timeout);
+        return Ok((TestStatus::CheckerFail(result.status), message));
+    }
+
+    // testlib writes its verdict message to stderr, but some checkers use stdout
+    let message = if result.stderr.is_empty() {
+        &result.stdout
+    } else {
+        &result.stderr
+    };
+    let message = String::from_utf8_lossy(message).trim().to_string();
+    Ok((judge_verdict(result.status), message))
+}
+
+/// Run the special judge on the program's output for a test case.
+fn check(
+    checker: &Program,
+    prog: &Program,
+    case: &str,
+    output: &[u8],
+) -> Result<(TestStatus, String)> {
+    let dir = check_dir(prog);
+    let in_path = uncompressed_test_file(prog, case, "in", &dir)?;
+    let ans_path = uncompressed_test_file(prog, case, "out", &dir)?;
+    let out_path = dir.join(format!("{case}.actual"));
+    write_check_file(&out_path, &mut Cursor::new(output))?;
+    run_checker(checker, &in_path, &out_path, &ans_path)
+}
+
+/// Get the verdict for how the program ran, before looking at its output.
+/// Returns `None` if the program exited successfully within the limits, or
+/// with `expected_code` if the case expects an exit code.
+pub(super) fn run_verdict(
+    execution: &Execution,
+    limits: &Limits,
+    expected_code: Option<i32>,
+) -> Option<TestStatus> {
+    let status = &execution.status;
+    if execution.output_exceeded {
+        Some(TestStatus::OutputLimit)
+    } else if execution.hard_timeout(limits) {
+        Some(TestStatus::Timeout)
+    } else if execution.forbidden_syscall(limits) {
+        Some(TestStatus::ForbiddenSyscall)
+    } else if execution.memory_exceeded(limits) {
+        Some(TestStatus::MemoryLimit)
+    } else if let (Some(code), RunResult::Success | RunResult::ExitCode(_)) =
+        (expected_code, status)
+    {
+        if status.as_code() == code {
+            None
+        } else {
+            Some(TestStatus::WrongExitCode {
+                expected: code,
+                actual: status.clone(),
+            })
+        }
+    } else if !status.is_success() {
+        Some(TestStatus::Crash(status.clone()))
+    } else {
+        None
+    }
+}
+
+/// Get the first name of the form `{prefix}{n}`, counting from 1, that is
+/// not yet used by a test case of the program.
+pub fn next_case_name(prog: &Program, prefix: &str) -> Result<String> {
+    let cases = get_test_cases(prog)?;
+    let name = (1..)
+        .map(|n| format!("{prefix}{n}"))
+        .find(|name| !cases.contains(name))
+        .unwrap();
+    Ok(name)
+}
+
+/// Add a test case to the program's test directory, with the given input and
+/// expected output. Returns the name of the new case, which is the first free
+/// name starting with `prefix`. If `compress` is set, files of at least
+/// [`COMPRESS_THRESHOLD`] bytes are compressed with xz.
+pub fn add_test_case(
+    prog: &Program,
+    prefix: &str,
+    input: &[u8],
+    output: &[u8],
+    compress: bool,
+) -> Result<String> {
+    let case = next_case_name(prog, prefix)?;
+    for (ext, data) in [("in", input), ("out", output)] {
+        let path = test_file_path(prog, &case, ext);
+        if compress && data.len() >= COMPRESS_THRESHOLD {
+            let mut path = OsString::from(path);
+            path.push(XZ.suffix);
+            write_compressed_test_file(Path::new(&path), data, Some(XZ))?;
+        } else {
+            write_test_file(&path, data)?;
+        }
+    }
+    Ok(case)
+}
+
+/// Get the directory where files for the checker and interactor are kept.
+pub(super) fn check_dir(prog: &Program) -> PathBuf {
+    prog.repository()
+        .build_path()
+        .join("check")
+        .join(prog.name())
+}
+
+/// Get the directory where the actual output of failed test cases is kept.
+pub(super) fn output_dir(prog: &Program) -> PathBuf {
+    prog.repository().build_path().join("out").join(prog.name())
+}
+
+/// Keep the actual output and error stream of a failed test case on disk, as
+/// `{case}.actual` and `{case}.stderr` in the output directory, or delete
+/// them if the case passed. Returns the path of the actual output.
+fn save_actual_output(
+    prog: &Program,
+    case: &str,
+    passed: bool,
+    execution: &Execution,
+) -> Result<Option<PathBuf>> {
+    let dir = output_dir(prog);
+    let out_path = dir.join(format!("{case}.actual"));
+    let err_path = dir.join(format!("{case}.stderr"));
+    if passed {
+        for path in [&out_path, &err_path] {
+            match fs::remove_file(path) {
+                Ok(()) => {}
+                Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("failed to delete file {:?}", path))
+                }
+            }
+        }
+        Ok(None)
+    } else {
+        write_test_file(&out_path, &execution.stdout)?;
+        write_test_file(&err_path, &execution.stderr)?;
+        Ok(Some(out_path))
+    }
+}
+
+/// Convert the exit status of a testlib-style checker or interactor to the
+/// test verdict.
+pub(super) fn judge_verdict(run_status: RunResult) -> TestStatus {
+    match run_status.as_code() {
+        0 => TestStatus::Pass,
+        // 7 is testlib's partial score verdict, which is not a pass
+        1 | 7 => TestStatus::Wrong,
+        2 => TestStatus::PresentationError,
+        _ => TestStatus::CheckerFail(run_status),
+    }
+}
+
+/// Read the command-line arguments for the test case from its `.args` file.
+pub(super) fn load_test_args(prog: &Program, case: &str) -> Result<Vec<String>> {
+    if let Some(args) = prog.case_config(case).and_then(|c| c.args.as_ref()) {
+        return Ok(args.clone());
+    }
+    match open_optional_test_file(prog, case, "args")? {
         Some(mut f) => {
             let mut s = String::new();
             f.read_to_string(&mut s)?;
-            s.split_whitespace()
+            Ok(s.split_whitespace()
                 .filter(|s| !s.is_empty())
                 .map(str::to_string)
-                .collect()
+                .collect())
         }
-        None => vec![],
-    };
-    Ok(TestData {
-        args,
+        None => Ok(vec![]),
+    }
+}
+
+/// Open the files of all accepted outputs of a test case: `NAME.out`, then
+/// `NAME.out.1`, `NAME.out.2` and so on, as long as they exist.
+fn open_output_files(prog: &Program, case: &str) -> Result<Vec<(String, Box<dyn Send + Read>)>> {
+    let mut files = vec![];
+    if let Some(file) = open_optional_test_file(prog, case, "out")? {
+        files.push(("out".to_string(), file));
+    }
+    for n in 1.. {
+        let extension = format!("out.{n}");
+        match open_optional_test_file(prog, case, &extension)? {
+            Some(file) => files.push((extension, file)),
+            None => break,
+        }
+    }
+    Ok(files)
+}
+
+/// Read the exit code that the program is expected to exit with, from the
+/// case's `.code` file.
+fn load_expected_code(prog: &Program, case: &str) -> Result<Option<i32>> {
+    match open_optional_test_file(prog, case, "code")? {
+        Some(mut f) => {
+            let mut s = String::new();
+            f.read_to_string(&mut s)?;
+            match s.trim().parse() {
+                Ok(code) => Ok(Some(code)),
+                Err(_) => bail!("invalid exit code {:?} in '{}.code' file", s.trim(), case),
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+/// Get the name of an expected output file to show to the user.
+fn output_file_name(prog: &Program, case: &str, extension: &str) -> String {
+    let path = prog
+        .test_layout()
+        .file_path(prog.test_path(), case, extension);
+    path.to_string_lossy().into_owned()
+}
+
+/// Open the input and output files for the test case.
+fn load_test_data_for_case(prog: &Program, case: &str) -> Result<TestData> {
+    let data = TestData {
+        args: load_test_args(prog, case)?,
         in_file: open_test_file(prog, case, "in")?,
-        out_file: open_test_file(prog, case, "out")?,
-    })
+        out_files: open_output_files(prog, case)?,
+        code: load_expected_code(prog, case)?,
+        err_file: open_optional_test_file(prog, case, "err")?,
+    };
+    // A case may only expect an exit code or a diagnostic
+    if data.out_files.is_empty() && data.code.is_none() && data.err_file.is_none() {
+        bail!("could not find '{}.out' file for {}", case, prog);
+    }
+    Ok(data)
 }
 
 /// Compile and test the program. The program's output is compared
-/// to the expected output, and its error stream is discarded.
+/// to the expected output. If the case has a `NAME.err` file, the error
+/// stream is compared to it, and if it has a `NAME.code` file, the program
+/// must exit with that code instead of success.
 pub fn test(prog: &Program, case: &str) -> Result<TestResult> {
-    // Read the entire input file, to avoid slowdowns due to XZ decoding
+    if let Some(interactor) = prog.interactor()? {
+        return test_interactive(prog, &interactor, case);
+    }
+
+    // Read the entire input file, to avoid slowdowns due to decompression
     let TestData {
         args,
-        mut in_file,
-        mut out_file,
+        in_file,
+        out_files,
+        code,
+        err_file,
     } = load_test_data_for_case(prog, case)?;
+    let checker = prog.checker()?;
 
-    // Start the program
+    // Run the program
+    let limits = Limits::for_case(prog, case);
     let mut cmd = get_run_command(prog);
-    cmd.stdin(Stdio::piped());
-    cmd.stdout(Stdio::piped());
-    cmd.stderr(Stdio::piped());
     cmd.args(&args);
-    let begin = Instant::now();
-    let mut child = cmd
-        .spawn()
-        .with_context(|| format!("failed to run command {:?}", cmd))?;
-
-    // Feed input file into stdin
-    let mut stdin = child.stdin.take().unwrap();
-    let in_thread = thread::spawn(move || match io::copy(&mut in_file, &mut stdin) {
-        // This thread copies the input data to the process's stdin.
-        Ok(_) => Ok(()),
-        Err(ref e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
-        Err(e) => Err(e),
-    });
-
-    // Capture the data from stdout
-    let mut stdout = child.stdout.take().unwrap();
-    // The output is sent through this channel. We can't just return this value
-    // when the thread exits, because that doesn't allow us to `recv_timeout`.
-    let (send, recv) = mpsc::channel();
-    let out_thread: JoinHandle<io::Result<()>> = thread::spawn(move || {
-        // This thread reads the output from the child process.
-        let mut act_output = vec![];
-        stdout.read_to_end(&mut act_output)?;
-        send.send(act_output).unwrap();
-        Ok(())
-    });
-
-    // Capture the data from stderr
-    let mut stderr = child.stderr.take().unwrap();
-    let err_thread: JoinHandle<io::Result<Vec<u8>>> = thread::spawn(move || {
-        let mut buf = vec![];
-        stderr.read_to_end(&mut buf)?;
-        Ok(buf)
-    });
-
-    // Get the result with the hard timeout
-    let result = recv.recv_timeout(Duration::from_millis(
-        prog.repository().config().hard_timeout,
-    ));
-    // Calculate the end time and time taken
-    let end = Instant::now();
-    let dur = end - begin;
-    let timeout = (dur.as_secs() * 1000 + u64::from(dur.subsec_millis()))
-        >= prog.repository().config().soft_timeout;
+    let execution = execute(&mut cmd, in_file, &limits)?;
 
     // Test outcome
-    let status = match result {
-        Ok(act_output) => {
-            // Program exited before the hard timeout
+    let mut message = None;
+    let mut expected = None;
+    let mut output_name = None;
+    let mut stderr_expected = None;
+    let status = if let Some(status) = run_verdict(&execution, &limits, code) {
+        status
+    } else if let Some(checker) = &checker {
+        let (status, msg) = check(checker, prog, case, &execution.stdout)
+            .with_context(|| format!("failed to check output with {}", checker))?;
+        message = Some(msg);
+        status
+    } else if out_files.is_empty() {
+        // Only the exit code or the error stream is checked
+        TestStatus::Pass
+    } else {
+        let mut outputs = vec![];
+        for (extension, mut file) in out_files {
             let mut exp_output = vec![];
-            out_file
-                .read_to_end(&mut exp_output)
+            file.read_to_end(&mut exp_output)
                 .context("failed to read output file")?;
-            let run_status: RunResult = child.wait()?.into();
-            if !run_status.is_success() {
-                TestStatus::Crash(run_status)
-            } else if act_output == exp_output {
+            outputs.push((extension, exp_output));
+        }
+        let several = outputs.len() > 1;
+        let comparator = prog.case_comparator(case);
+        if let Some((extension, _)) = outputs
+            .iter()
+            .find(|(_, exp_output)| comparator.matches(&execution.stdout, exp_output))
+        {
+            if several {
+                output_name = Some(output_file_name(prog, case, extension));
+            }
+            TestStatus::Pass
+        } else {
+            // Show the diff with the closest of the outputs
+            let (extension, exp_output) = outputs
+                .into_iter()
+                .rev()
+                .max_by_key(|(_, exp_output)| similarity(&execution.stdout, exp_output))
+                .unwrap();
+            if several {
+                output_name = Some(output_file_name(prog, case, &extension));
+            }
+            expected = Some(exp_output);
+            TestStatus::Wrong
+        }
+    };
+
+    // Check the error stream only if everything else is correct
+    let status = match err_file {
+        Some(mut err_file) if status == TestStatus::Pass => {
+            let mut exp_stderr = vec![];
+            err_file
+                .read_to_end(&mut exp_stderr)
+                .context("failed to read error file")?;
+            if prog
+                .case_comparator(case)
+                .matches(&execution.stderr, &exp_stderr)
+            {
                 TestStatus::Pass
             } else {
-                TestStatus::Wrong
+                stderr_expected = Some(exp_stderr);
+                TestStatus::WrongStderr
             }
         }
-        Err(_) => {
-            // Program did not exit in time
-            child.kill().context("failed to kill child process")?;
-            TestStatus::Timeout
-        }
+        _ => status,
     };
 
-    // Let the threads finish
-    in_thread
-        .join()
-        .unwrap()
-        .context("error in input feeding thread")?;
-
-    out_thread
-        .join()
-        .unwrap()
-        .context("error in stdout capturing thread")?;
-
-    let stderr = err_thread
-        .join()
-        .unwrap()
-        .context("error in stdout capturing thread")?;
-
-    Ok(TestResult {
-        status,
-        time: dur,
-        timeout,
-        stderr,
-    })
+    let passed = status == TestStatus::Pass && !execution.soft_timeout(&limits);
+    let actual_path = save_actual_output(prog, case, passed, &execution)?;
+    let mut result = TestResult::from_execution(execution, &limits, status, expected);
+    result.message = message;
+    result.output_name = output_name;
+    result.actual_path = actual_path;
+    result.description = prog.case_config(case).and_then(|c| c.description.clone());
+    if let Some(expected) = stderr_expected {
+        result.mismatch = Some(Mismatch {
+            expected,
+            actual: result.stderr.clone(),
+        });
+    }
+    Ok(result)
 }
 
 /// Result of a program test.
 #[derive(Clone, Debug)]
 pub struct TestResult {
     pub status: TestStatus,
+    /// Real time taken by the program.
     pub time: Duration,
+    /// CPU time used by the program in user mode.
+    pub user_time: Duration,
+    /// CPU time spent in the kernel on behalf of the program.
+    pub system_time: Duration,
     pub timeout: bool,
+    /// Peak memory usage in bytes, or 0 if unknown.
+    pub memory: u64,
     pub stderr: Vec<u8>,
+    /// Message from the special judge, if the problem has one.
+    pub message: Option<String>,
+    /// The expected and actual output, if the output was wrong, or the
+    /// expected and actual error stream, if that was wrong.
+    pub mismatch: Option<Mismatch>,
+    /// File of the expected output that the output matched, or that it is
+    /// compared with in the diff, if the case has several.
+    pub output_name: Option<String>,
+    /// Path to the recorded conversation of an interactive test.
+    pub transcript: Option<PathBuf>,
+    /// Description of the test case from the problem's `tests.toml` file.
+    pub description: Option<String>,
+    /// Path to the saved actual output, if the test failed.
+    pub actual_path: Option<PathBuf>,
+}
+
+/// Output of a test that did not match the expected output.
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    pub expected: Vec<u8>,
+    pub actual: Vec<u8>,
 }
 
 impl TestResult {
+    /// Create the result of a test from the program's execution. If the
+    /// output was wrong, `expected` is the expected output, for showing a
+    /// diff.
+    pub(super) fn from_execution(
+        execution: Execution,
+        limits: &Limits,
+        status: TestStatus,
+        expected: Option<Vec<u8>>,
+    ) -> TestResult {
+        let timeout = execution.soft_timeout(limits);
+        let actual = execution.stdout;
+        TestResult {
+            status,
+            time: execution.time,
+            user_time: execution.usage.user_time,
+            system_time: execution.usage.system_time,
+            timeout,
+            memory: execution.usage.peak_memory,
+            stderr: execution.stderr,
+            mismatch: expected.map(|expected| Mismatch { expected, actual }),
+            message: None,
+            output_name: None,
+            transcript: None,
+            description: None,
+            actual_path: None,
+        }
+    }
+
+    /// Get the CPU time used by the program, in both user and kernel mode.
+    pub fn cpu_time(&self) -> Duration {
+        self.user_time + self.system_time
+    }
+
     pub fn passed(&self) -> bool {
         self.status == TestStatus::Pass && !self.timeout
     }
@@ -241,4 +710,43 @@ pub enum TestStatus {
     Wrong,
     Crash(RunResult),
     Timeout,
+    /// The program used more memory than the memory limit.
+    MemoryLimit,
+    /// The checker accepted the answer but not its formatting.
+    PresentationError,
+    /// The checker itself failed, e.g. because the expected answer is invalid.
+    CheckerFail(RunResult),
+    /// The program did not exit with the exit code in the case's `.code`
+    /// file.
+    WrongExitCode {
+        expected: i32,
+        actual: RunResult,
+    },
+    /// The program's error stream did not match the case's `.err` file.
+    WrongStderr,
+    /// The program was killed by the sandbox for making a system call that
+    /// is not allowed.
+    ForbiddenSyscall,
+    /// The program wrote more than the output limit, and was killed.
+    OutputLimit,
+}
+
+impl fmt::Display for TestStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestStatus::Pass => write!(f, "pass"),
+            TestStatus::Wrong => write!(f, "wrong"),
+            TestStatus::Crash(run_result) => write!(f, "crash with {}", run_result),
+            TestStatus::Timeout => write!(f, "timeout"),
+            TestStatus::MemoryLimit => write!(f, "memory limit"),
+            TestStatus::PresentationError => write!(f, "presentation"),
+            TestStatus::CheckerFail(run_result) => write!(f, "checker failed with {}", run_result),
+            TestStatus::WrongExitCode { expected, actual } => {
+                write!(f, "expected exit code {}, got {}", expected, actual)
+            }
+            TestStatus::WrongStderr => write!(f, "wrong stderr"),
+            TestStatus::ForbiddenSyscall => write!(f, "forbidden syscall"),
+            TestStatus::OutputLimit => write!(f, "output limit"),
+        }
+    }
 }
@@ -0,0 +1,76 @@
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use inotify::{Inotify, WatchMask};
+use walkdir::WalkDir;
+
+use crate::{Program, Repository};
+
+/// Watches the files that the result of a test run depends on.
+pub struct Watcher {
+    inotify: Inotify,
+}
+
+impl Watcher {
+    /// Start watching the repository's source directory and configuration
+    /// file, and the program's test directory, if there is a program.
+    pub fn new(repo: &Repository, prog: Option<&Program>) -> Result<Watcher> {
+        let mut watcher = Watcher {
+            inotify: Inotify::init().context("failed to initialize inotify")?,
+        };
+        watcher.add_dir(repo.source_path())?;
+        watcher.add(repo.config_path())?;
+        if let Some(prog) = prog {
+            watcher.add_dir(prog.test_path())?;
+        }
+        Ok(watcher)
+    }
+
+    /// Watch the file or directory, if it exists.
+    fn add(&mut self, path: &Path) -> Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let mask = WatchMask::CLOSE_WRITE
+            | WatchMask::CREATE
+            | WatchMask::DELETE
+            | WatchMask::MOVED_FROM
+            | WatchMask::MOVED_TO
+            | WatchMask::DELETE_SELF
+            | WatchMask::MOVE_SELF;
+        self.inotify
+            .watches()
+            .add(path, mask)
+            .with_context(|| format!("failed to watch {:?}", path))?;
+        Ok(())
+    }
+
+    /// Watch the directory and all of its subdirectories, since inotify does
+    /// not watch them by itself.
+    fn add_dir(&mut self, dir: &Path) -> Result<()> {
+        for ent in WalkDir::new(dir) {
+            let ent = match ent {
+                Ok(ent) => ent,
+                // The directory may not exist yet
+                Err(_) => continue,
+            };
+            if ent.file_type().is_dir() {
+                self.add(ent.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until one of the watched files changes.
+    pub fn wait(mut self) -> Result<()> {
+        let mut buffer = [0; 4096];
+        self.inotify
+            .read_events_blocking(&mut buffer)
+            .context("failed to read inotify events")?;
+        // Editors often write a file in several steps, so let them finish
+        thread::sleep(Duration::from_millis(100));
+        Ok(())
+    }
+}
@@ -0,0 +1,19 @@
+use anyhow::{bail, Result};
+
+use crate::{Program, Repository};
+
+/// Watches the files that the result of a test run depends on. Watching is
+/// only supported on Linux.
+pub struct Watcher;
+
+impl Watcher {
+    /// Fail, since files cannot be watched on this platform.
+    pub fn new(_repo: &Repository, _prog: Option<&Program>) -> Result<Watcher> {
+        bail!("watching for changes is only supported on Linux");
+    }
+
+    /// Wait for a change.
+    pub fn wait(self) -> Result<()> {
+        Ok(())
+    }
+}
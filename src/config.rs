@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use serde::Deserialize;
 
@@ -10,7 +10,17 @@ pub struct Config {
     pub build_dir: String,
     pub soft_timeout: u64,
     pub hard_timeout: u64,
+    pub timing: Timing,
+    pub memory_limit: u64,
+    pub output_limit: u64,
     pub buffering: bool,
+    pub jobs: usize,
+    pub test_layout: TestLayout,
+    /// File that new solutions are copied from, relative to the repository
+    /// root.
+    pub template: Option<String>,
+    pub sandbox: SandboxConfig,
+    pub comparator: Comparator,
     pub languages: HashMap<String, Language>,
 }
 
@@ -22,12 +32,71 @@ impl Default for Config {
             build_dir: "build".to_string(),
             soft_timeout: 2000,
             hard_timeout: 5000,
+            timing: Timing::Wall,
+            memory_limit: 0,
+            output_limit: 64,
             buffering: false,
+            jobs: 0,
+            test_layout: TestLayout::Auto,
+            template: None,
+            sandbox: Default::default(),
+            comparator: Default::default(),
             languages: Default::default(),
         }
     }
 }
 
+/// Which time counts against the timeouts.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Timing {
+    /// Real time elapsed between starting the program and its exit.
+    Wall,
+    /// CPU time used by the program, in both user and kernel mode.
+    Cpu,
+}
+
+/// How the test files of a program are named and arranged in its test
+/// directory.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestLayout {
+    /// Detect the layout from the files in the test directory.
+    Auto,
+    /// `NAME.in` and `NAME.out`.
+    Coman,
+    /// `data/**/NAME.in` and `data/**/NAME.ans`, as used by Kattis.
+    Kattis,
+    /// `NAME` and `NAME.a`, as used by Polygon.
+    Polygon,
+    /// `input/NAME` and `output/NAME`.
+    Folders,
+}
+
+/// Isolation of the programs under test from the rest of the system.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SandboxConfig {
+    /// Whether to run programs in the sandbox during tests and runs.
+    pub enabled: bool,
+    /// Maximum number of processes that the user may have while a program
+    /// runs, or 0 for no limit.
+    pub max_processes: u64,
+    /// Whether to kill programs that make system calls which solutions have
+    /// no use for, such as opening sockets.
+    pub seccomp: bool,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> SandboxConfig {
+        SandboxConfig {
+            enabled: false,
+            max_processes: 64,
+            seccomp: false,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, Deserialize)]
 #[serde(default)]
 pub struct Language {
@@ -36,3 +105,100 @@ pub struct Language {
     pub run: Vec<String>,
     pub debug: Vec<String>,
 }
+
+/// How the output of a program is compared to the expected output.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Comparator {
+    pub mode: CompareMode,
+    /// Maximum absolute difference between numbers in `float` mode.
+    pub absolute_tolerance: f64,
+    /// Maximum difference between numbers in `float` mode, relative to the
+    /// expected number.
+    pub relative_tolerance: f64,
+}
+
+impl Default for Comparator {
+    fn default() -> Comparator {
+        Comparator {
+            mode: CompareMode::Exact,
+            absolute_tolerance: 1e-6,
+            relative_tolerance: 1e-6,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompareMode {
+    /// The outputs must be byte-for-byte identical.
+    Exact,
+    /// The outputs must have the same whitespace-separated tokens.
+    Tokens,
+    /// Like `Tokens`, but numbers may differ within a tolerance.
+    Float,
+    /// Like `Tokens`, but ASCII letters are compared case-insensitively.
+    CaseInsensitive,
+    /// The outputs must have the same lines, in any order.
+    UnorderedLines,
+}
+
+/// Per-problem settings, read from the `tests.toml` file in a program's test
+/// directory.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ProblemConfig {
+    /// Address of the problem statement, recorded by `coman listen`.
+    pub url: Option<String>,
+    /// Source file of the special judge, relative to the source directory.
+    pub checker: Option<String>,
+    /// Source file of the interactor for interactive problems, relative to
+    /// the source directory.
+    pub interactor: Option<String>,
+    /// Overrides the repository's soft timeout for this problem.
+    pub soft_timeout: Option<u64>,
+    /// Overrides the repository's hard timeout for this problem.
+    pub hard_timeout: Option<u64>,
+    /// Overrides the repository's memory limit for this problem.
+    pub memory_limit: Option<u64>,
+    /// Overrides the repository's comparator for this problem.
+    pub comparator: Option<Comparator>,
+    /// Overrides the repository's test layout for this problem.
+    pub test_layout: Option<TestLayout>,
+    /// Test groups, by name.
+    pub groups: BTreeMap<String, GroupConfig>,
+    /// Settings of individual test cases. The cases listed here are run
+    /// first, in this order.
+    pub cases: Vec<CaseConfig>,
+}
+
+/// Settings of a single test case.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CaseConfig {
+    pub name: String,
+    /// What the case tests, shown when it fails.
+    pub description: Option<String>,
+    /// Whether the case is a sample from the problem statement.
+    pub sample: bool,
+    /// Overrides the repository's soft timeout for this case.
+    pub soft_timeout: Option<u64>,
+    /// Overrides the repository's hard timeout for this case.
+    pub hard_timeout: Option<u64>,
+    /// Arguments to the program. These are used instead of the case's
+    /// `.args` file.
+    pub args: Option<Vec<String>>,
+    /// Overrides the problem's comparator for this case.
+    pub comparator: Option<Comparator>,
+}
+
+/// Settings of a group of test cases that is scored as a whole.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GroupConfig {
+    /// Points awarded if every case in the group passes.
+    pub points: f64,
+    /// Cases in the group. If not given, the group consists of the cases in
+    /// the subdirectory with the group's name.
+    pub cases: Option<Vec<String>>,
+}
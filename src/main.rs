@@ -1,7 +1,13 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
+use std::io::{self, Read};
+use std::num::NonZeroUsize;
 use std::path::Path;
 use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
 
 use anyhow::{bail, Context, Result};
 use args::{Arguments, UsageError};
@@ -45,14 +51,230 @@ fn do_build(program: &Program, debug: bool, output: Option<&str>) -> Result<()>
     Ok(())
 }
 
-fn do_test(prog: &Program, case: &str) -> Result<bool> {
+fn do_test(prog: &Program, case: &str, side_by_side: bool) -> Result<bool> {
     ui::print_test_case(case);
     let result = command::test(prog, case)
         .with_context(|| format!("failed to run test case {:?} on program {}", case, prog))?;
-    ui::print_test_result(&result);
+    ui::print_test_result(&result, side_by_side);
     Ok(result.passed())
 }
 
+/// Run the test cases, using up to `jobs` threads. The results are printed
+/// in the same order as the cases. `case_groups` has the indices of the
+/// groups that each case is in. If `skip` is set, a case is skipped once every
+/// group that it is in has a failed case. Returns whether each case passed, or
+/// `None` if it was skipped.
+fn do_tests(
+    prog: &Program,
+    cases: &[&str],
+    case_groups: &[Vec<usize>],
+    jobs: usize,
+    skip: bool,
+    side_by_side: bool,
+) -> Result<Vec<Option<bool>>> {
+    let num_groups = case_groups.iter().flatten().max().map_or(0, |&g| g + 1);
+    let failed_groups: Vec<_> = (0..num_groups).map(|_| AtomicBool::new(false)).collect();
+    let should_skip = |i: usize| {
+        skip && !case_groups[i].is_empty()
+            && case_groups[i]
+                .iter()
+                .all(|&g| failed_groups[g].load(Ordering::SeqCst))
+    };
+    let record_failure = |i: usize| {
+        for &g in &case_groups[i] {
+            failed_groups[g].store(true, Ordering::SeqCst);
+        }
+    };
+
+    let mut results = vec![];
+    if jobs <= 1 {
+        for (i, case) in cases.iter().enumerate() {
+            if should_skip(i) {
+                ui::print_test_case(case);
+                ui::print_skipped();
+                results.push(None);
+                continue;
+            }
+            let passed = do_test(prog, case, side_by_side)?;
+            if !passed {
+                record_failure(i);
+            }
+            results.push(Some(passed));
+        }
+        return Ok(results);
+    }
+
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    thread::scope(|s| {
+        let (send, recv) = mpsc::channel();
+        for _ in 0..jobs.min(cases.len()) {
+            let send = send.clone();
+            let (next, stop) = (&next, &stop);
+            let (should_skip, record_failure) = (&should_skip, &record_failure);
+            s.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= cases.len() || stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let test_result = if should_skip(i) {
+                    Ok(None)
+                } else {
+                    command::test(prog, cases[i])
+                        .map(|r| {
+                            if !r.passed() {
+                                record_failure(i);
+                            }
+                            Some(r)
+                        })
+                        .with_context(|| {
+                            format!("failed to run test case {:?} on program {}", cases[i], prog)
+                        })
+                };
+                if send.send((i, test_result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(send);
+
+        // Results may arrive out of order, so hold on to them until the
+        // preceding ones have been printed
+        let mut pending = BTreeMap::new();
+        for (i, test_result) in recv {
+            pending.insert(i, test_result);
+            while let Some(test_result) = pending.remove(&results.len()) {
+                let test_result = match test_result {
+                    Ok(r) => r,
+                    Err(e) => {
+                        stop.store(true, Ordering::SeqCst);
+                        return Err(e);
+                    }
+                };
+                ui::print_test_case(cases[results.len()]);
+                match test_result {
+                    Some(test_result) => {
+                        ui::print_test_result(&test_result, side_by_side);
+                        results.push(Some(test_result.passed()));
+                    }
+                    None => {
+                        ui::print_skipped();
+                        results.push(None);
+                    }
+                }
+            }
+        }
+        Ok(results)
+    })
+}
+
+/// Write the program's output as the expected output of the test cases, or of
+/// the cases without an expected output if none are given.
+fn do_bless(prog: &Program, tests: &[&str]) -> Result<bool> {
+    let cases = if tests.is_empty() {
+        let mut cases = command::get_test_cases(prog)?;
+        cases.retain(|case| !command::has_test_file(prog, case, "out"));
+        command::sort_test_cases(prog, &mut cases);
+        cases
+    } else {
+        tests.iter().map(|s| s.to_string()).collect()
+    };
+    if cases.is_empty() {
+        stepln!("BLESS", "all test cases have an expected output");
+        return Ok(true);
+    }
+
+    let mut result = true;
+    for case in &cases {
+        step!("BLESS", "{}: ", case);
+        let blessed = command::bless(prog, case, &mut |path| {
+            ui::confirm(&format!("overwrite {}?", path.display()))
+        })
+        .with_context(|| format!("failed to bless test case {:?} of {}", case, prog))?;
+        match blessed {
+            command::Blessed::Written(path) => eprintln!("wrote {}", path.display()),
+            command::Blessed::Kept(path) => eprintln!("kept {}", path.display()),
+            command::Blessed::Failed(test_result) => {
+                ui::print_test_result(&test_result, false);
+                result = false;
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Build the program, along with the checker and interactor of its problem.
+fn do_build_for_tests(prog: &Program) -> Result<()> {
+    do_build(prog, false, None)?;
+    if let Some(checker) = prog.checker()? {
+        do_build(&checker, false, None)?;
+    }
+    if let Some(interactor) = prog.interactor()? {
+        do_build(&interactor, false, None)?;
+    }
+    Ok(())
+}
+
+/// Run the test cases, or all of the program's test cases if none are given,
+/// and print the results. Returns whether all of them passed.
+fn do_test_suite(
+    program: &Program,
+    tests: &[&str],
+    jobs: Option<usize>,
+    samples: bool,
+    skip: bool,
+    side_by_side: bool,
+) -> Result<bool> {
+    let jobs = match (jobs, program.repository().config().jobs) {
+        (Some(jobs), _) => jobs,
+        (None, 0) => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        (None, jobs) => jobs,
+    };
+
+    let cases = if tests.is_empty() {
+        // Testing all cases
+        let mut cases = command::get_test_cases(program)?;
+        if cases.is_empty() {
+            // No cases found
+            bail!("no test cases found in {:?}", program.test_path());
+        }
+        if let Some(c) = program
+            .problem_config()
+            .cases
+            .iter()
+            .find(|c| !cases.contains(&c.name))
+        {
+            bail!("test case {:?} in tests.toml does not exist", c.name);
+        }
+        if samples {
+            cases.retain(|case| program.case_config(case).is_some_and(|c| c.sample));
+            if cases.is_empty() {
+                bail!("no sample test cases for {}", program);
+            }
+        }
+        command::sort_test_cases(program, &mut cases);
+        cases
+    } else {
+        tests.iter().map(|s| s.to_string()).collect()
+    };
+
+    let groups = command::get_test_groups(program, &cases)?;
+    let case_groups: Vec<Vec<usize>> = cases
+        .iter()
+        .map(|case| {
+            (0..groups.len())
+                .filter(|&g| groups[g].cases.contains(case))
+                .collect()
+        })
+        .collect();
+    let case_names: Vec<_> = cases.iter().map(String::as_str).collect();
+    let results = do_tests(program, &case_names, &case_groups, jobs, skip, side_by_side)?;
+    if !groups.is_empty() {
+        ui::print_group_results(&groups, &cases, &results);
+    }
+    Ok(results.iter().all(|&r| r == Some(true)))
+}
+
 fn try_main(args: Arguments) -> Result<bool> {
     // init is the only command that doesn't require an existing repository
     if args.subcommand == Subcommand::Init {
@@ -96,33 +318,226 @@ fn try_main(args: Arguments) -> Result<bool> {
             Ok(result.is_success())
         }
 
-        Subcommand::Test { program, tests } => {
+        Subcommand::Test {
+            program,
+            tests,
+            jobs,
+            bless,
+            samples,
+            skip,
+            side_by_side,
+        } => {
+            let program = get_program(&repo, program)?;
+            do_build_for_tests(&program)?;
+            if bless {
+                return do_bless(&program, &tests);
+            }
+            do_test_suite(&program, &tests, jobs, samples, skip, side_by_side)
+        }
+
+        Subcommand::TestAdd {
+            program,
+            editor,
+            delimiter,
+            compress,
+        } => {
+            let program = get_program(&repo, program)?;
+            let data = if editor {
+                command::edit_test_data(&program, delimiter).context("failed to edit test case")?
+            } else {
+                let mut data = vec![];
+                io::stdin()
+                    .read_to_end(&mut data)
+                    .context("failed to read test case from stdin")?;
+                data
+            };
+            let (input, output) = command::split_test_data(&data, delimiter)?;
+            if input.trim_ascii().is_empty() && output.trim_ascii().is_empty() {
+                bail!("test case is empty");
+            }
+
+            let case = command::add_test_case(&program, "", input, output, compress)
+                .with_context(|| format!("failed to add test case to {}", program))?;
+            stepln!("ADDED", "test case {} for {}", case, program.name());
+            Ok(true)
+        }
+
+        Subcommand::Bench {
+            program,
+            tests,
+            runs,
+            warmup,
+        } => {
             let program = get_program(&repo, program)?;
             do_build(&program, false, None)?;
 
-            let mut result = true;
-            if tests.is_empty() {
-                // Testing all cases
+            let cases = if tests.is_empty() {
                 let mut cases = command::get_test_cases(&program)?;
                 if cases.is_empty() {
-                    // No cases found
                     bail!("no test cases found in {:?}", program.test_path());
-                } else {
-                    alphanumeric_sort::sort_str_slice(&mut cases);
-                    for case in &cases {
-                        if !do_test(&program, case)? {
-                            result = false;
-                        }
-                    }
                 }
+                command::sort_test_cases(&program, &mut cases);
+                cases
             } else {
-                for case in tests {
-                    if !do_test(&program, case)? {
-                        result = false;
+                tests.iter().map(|s| s.to_string()).collect()
+            };
+            let mut trusted = true;
+            for case in &cases {
+                let bench = command::bench(&program, case, warmup, runs, &mut |done| {
+                    step!("BENCH", "{}: run {}/{}\r", case, done, runs);
+                })
+                .with_context(|| format!("failed to benchmark test case {:?}", case))?;
+                ui::print_bench_result(case, &bench);
+                trusted &= !bench.wall.is_noisy() && !bench.cpu.is_noisy();
+            }
+            if !trusted {
+                stepln!(
+                    "BENCH",
+                    "some timings are noisy; try more runs or a quieter machine"
+                );
+            }
+            Ok(true)
+        }
+
+        Subcommand::Stress {
+            program,
+            generator,
+            reference,
+            count,
+            seed,
+        } => {
+            let program = get_program(&repo, program)?;
+            let generator = repo.get_program(generator)?;
+            let reference = repo.get_program(reference)?;
+            do_build(&program, false, None)?;
+            do_build(&generator, false, None)?;
+            do_build(&reference, false, None)?;
+            if let Some(checker) = program.checker()? {
+                do_build(&checker, false, None)?;
+            }
+
+            let seeds = match count {
+                Some(count) => seed..seed.saturating_add(count),
+                None => seed..u64::MAX,
+            };
+            for seed in seeds {
+                step!("STRESS", "seed {}\r", seed);
+                let counterexample = command::stress(&program, &generator, &reference, seed)
+                    .with_context(|| format!("failed to stress test program {}", program))?;
+                if let Some(counterexample) = counterexample {
+                    let case = command::add_test_case(
+                        &program,
+                        "stress-",
+                        &counterexample.input,
+                        &counterexample.expected,
+                        false,
+                    )
+                    .context("failed to save counterexample")?;
+                    ui::print_test_case(&case);
+                    ui::print_test_result(&counterexample.result, false);
+                    stepln!("SAVED", "seed {} as test case {}", seed, case);
+                    return Ok(false);
+                }
+            }
+            stepln!("STRESS", "no counterexample found");
+            Ok(true)
+        }
+
+        Subcommand::Shrink {
+            program,
+            case,
+            reference,
+        } => {
+            let program = get_program(&repo, program)?;
+            let reference = reference.map(|r| repo.get_program(r)).transpose()?;
+            do_build(&program, false, None)?;
+            if let Some(reference) = &reference {
+                do_build(reference, false, None)?;
+            }
+            if let Some(checker) = program.checker()? {
+                do_build(&checker, false, None)?;
+            }
+
+            let shrunk = command::shrink(&program, case, reference.as_ref(), &mut |size| {
+                step!("SHRINK", "{}: {} bytes\x1b[K\r", case, size);
+            })
+            .with_context(|| format!("failed to shrink test case {:?} of {}", case, program))?;
+            ui::print_test_case(&shrunk.case);
+            ui::print_test_result(&shrunk.result, false);
+            stepln!(
+                "SAVED",
+                "{} bytes of {} as test case {}",
+                shrunk.input.len(),
+                shrunk.original_size,
+                shrunk.case,
+            );
+            Ok(true)
+        }
+
+        Subcommand::Watch { program, case } => {
+            let mut repo = repo;
+            loop {
+                ui::clear_screen();
+                // The configuration may have changed since the last round
+                match Repository::read(repo.root()) {
+                    Ok(new_repo) => repo = new_repo,
+                    Err(e) => eprintln!("coman: {:?}", e),
+                }
+                // Without a program given, follow the most recently saved one
+                let prog = get_program(&repo, program);
+                let watcher = command::Watcher::new(&repo, prog.as_ref().ok())?;
+                let result = prog.and_then(|prog| {
+                    do_build_for_tests(&prog)?;
+                    let tests: Vec<_> = case.into_iter().collect();
+                    do_test_suite(&prog, &tests, None, false, false, false)
+                });
+                if let Err(e) = result {
+                    eprintln!("coman: {:?}", e);
+                }
+                stepln!("WATCH", "waiting for changes");
+                watcher.wait()?;
+            }
+        }
+
+        Subcommand::Listen { port } => {
+            let listener = command::Listener::bind(port)?;
+            stepln!("LISTEN", "port {}", port);
+            loop {
+                let problem = match listener.receive() {
+                    Ok(problem) => problem,
+                    Err(e) => {
+                        eprintln!("coman: {:?}", e);
+                        continue;
+                    }
+                };
+                stepln!("RECEIVE", "{} ({})", problem.name, problem.url);
+                match command::create_problem(&repo, &problem) {
+                    Ok((program, created)) => {
+                        if created {
+                            stepln!("CREATE", "{}", program.name());
+                        }
+                        stepln!("SAMPLES", "{} for {}", problem.tests.len(), program.name());
                     }
+                    Err(e) => eprintln!("coman: failed to create problem: {:?}", e),
                 }
             }
-            Ok(result)
+        }
+
+        Subcommand::ImportSamples { file, program } => {
+            let program = get_program(&repo, program)?;
+            let (site, cases) = command::import_samples(&program, Path::new(file))
+                .with_context(|| format!("failed to import samples for {}", program))?;
+            stepln!(
+                "IMPORT",
+                "{} samples from {} for {}",
+                cases.len(),
+                site,
+                program.name()
+            );
+            for case in cases {
+                stepln!("ADDED", "test case {}", case);
+            }
+            Ok(true)
         }
 
         Subcommand::Debug { program } => {
@@ -178,7 +593,14 @@ Commands:
     clean|c [SOLUTION | --all]
     debug|d [SOLUTION]
     run|r [SOLUTION]
-    test|t [SOLUTION] [TEST ...]
+    test|t [-j JOBS] [-s] [-y] [--samples] [--bless] [SOLUTION] [TEST ...]
+    test|t add [-e] [-d DELIM] [-z] [SOLUTION]
+    bench [-n RUNS] [-w WARMUP] [SOLUTION] [TEST ...]
+    stress|s -g GEN -r REF [-n COUNT] [--seed SEED] [SOLUTION]
+    shrink [-r REF] [SOLUTION] CASE
+    watch|w [-c CASE] [SOLUTION]
+    listen [-p PORT]
+    import-samples [-p SOLUTION] FILE
     cmake
 "
             );
@@ -8,8 +8,12 @@ use anyhow::{bail, Context, Result};
 use if_chain::if_chain;
 use walkdir::WalkDir;
 
+use crate::CaseConfig;
+use crate::Comparator;
 use crate::Config;
 use crate::Language;
+use crate::ProblemConfig;
+use crate::TestLayout;
 
 /// Finds the root directory of the contests repository.
 ///
@@ -136,7 +140,7 @@ impl Repository {
     /// Get a `Program` from the path to its source code. Returns
     /// `None` if the path is outside of the source directory or if it
     /// does not exist.
-    pub fn get_program<P: AsRef<Path>>(&self, path: P) -> Result<Program> {
+    pub fn get_program<P: AsRef<Path>>(&self, path: P) -> Result<Program<'_>> {
         let path = path.as_ref();
         let path = match path.canonicalize() {
             Ok(p) => p,
@@ -153,20 +157,27 @@ impl Repository {
             .with_context(|| format!("file is not inside repository: {:?}", path))?;
 
         let mut src = self.source_path().to_path_buf();
-        src.push(&path);
+        src.push(path);
         let mut test = self.test_path().to_path_buf();
-        test.push(&path);
+        test.push(path);
         while test.extension().is_some() {
             test.set_extension("");
         }
         let mut build_release = self.build_release_path().to_path_buf();
-        build_release.push(&path);
+        build_release.push(path);
         let mut build_debug = self.build_debug_path().to_path_buf();
-        build_debug.push(&path);
+        build_debug.push(path);
+        let config = read_problem_config(&test)?;
+        let layout = config
+            .test_layout
+            .unwrap_or(self.config.test_layout)
+            .resolve(&test);
 
         Ok(Program {
             repo: self,
             path: path.to_path_buf(),
+            config,
+            layout,
             src,
             test,
             build_release,
@@ -176,7 +187,7 @@ impl Repository {
 
     /// Get the `Program` that was most recently modified. Returns
     /// `None` if no program could be found.
-    pub fn find_recent_program(&self) -> Result<Program> {
+    pub fn find_recent_program(&self) -> Result<Program<'_>> {
         let mut best_time = SystemTime::UNIX_EPOCH;
         let mut best_prog = None;
         for ent in WalkDir::new(self.source_path()).into_iter().flatten() {
@@ -202,24 +213,96 @@ impl Repository {
     }
 }
 
+/// Read the `tests.toml` file in a program's test directory. Returns the
+/// default settings if the file does not exist.
+fn read_problem_config(test: &Path) -> Result<ProblemConfig> {
+    let path = test.join("tests.toml");
+    match File::open(&path) {
+        Ok(mut f) => {
+            let mut s = String::new();
+            f.read_to_string(&mut s)
+                .with_context(|| format!("failed to read {:?}", path))?;
+            toml::from_str(&s).with_context(|| format!("failed to parse {:?}", path))
+        }
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(ProblemConfig::default()),
+        Err(e) => Err(e).with_context(|| format!("failed to read {:?}", path)),
+    }
+}
+
 /// A struct representing a program in a repository.
 ///
 /// This struct is immutable.
 pub struct Program<'a> {
     repo: &'a Repository,
     path: PathBuf,
+    config: ProblemConfig,
+    layout: TestLayout,
     src: PathBuf,
     test: PathBuf,
     build_release: PathBuf,
     build_debug: PathBuf,
 }
 
-impl Program<'_> {
+impl<'a> Program<'a> {
     /// Get the `Repository` in which this program is contained.
-    pub fn repository(&self) -> &Repository {
+    pub fn repository(&self) -> &'a Repository {
         self.repo
     }
 
+    /// Get the program's problem settings from its `tests.toml` file.
+    pub fn problem_config(&self) -> &ProblemConfig {
+        &self.config
+    }
+
+    /// Get the comparator used for this program's output, taking the
+    /// problem's override into account.
+    pub fn comparator(&self) -> &Comparator {
+        self.config
+            .comparator
+            .as_ref()
+            .unwrap_or(&self.repo.config().comparator)
+    }
+
+    /// Get the settings of a test case from the problem's `tests.toml` file,
+    /// if it has any.
+    pub fn case_config(&self, case: &str) -> Option<&CaseConfig> {
+        self.config.cases.iter().find(|c| c.name == case)
+    }
+
+    /// Get the comparator used for this program's output on a test case,
+    /// taking the case's override into account.
+    pub fn case_comparator(&self, case: &str) -> &Comparator {
+        self.case_config(case)
+            .and_then(|c| c.comparator.as_ref())
+            .unwrap_or_else(|| self.comparator())
+    }
+
+    /// Get the special judge that checks this program's output, if the
+    /// problem has one.
+    pub fn checker(&self) -> Result<Option<Program<'a>>> {
+        self.helper_program(&self.config.checker)
+            .with_context(|| format!("failed to find checker for {}", self))
+    }
+
+    /// Get the interactor that this program talks to during tests, if the
+    /// problem is interactive.
+    pub fn interactor(&self) -> Result<Option<Program<'a>>> {
+        self.helper_program(&self.config.interactor)
+            .with_context(|| format!("failed to find interactor for {}", self))
+    }
+
+    /// Get a program named in the problem settings by its path relative to
+    /// the source directory.
+    fn helper_program(&self, name: &Option<String>) -> Result<Option<Program<'a>>> {
+        match name {
+            Some(name) => {
+                let path = self.repo.source_path().join(name);
+                Ok(Some(self.repo.get_program(path)?))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// Get the name of the program.
     pub fn name(&self) -> &str {
         self.path.to_str().unwrap()
@@ -242,6 +325,11 @@ impl Program<'_> {
         &self.test
     }
 
+    /// Get the layout of the files in the program's test directory.
+    pub fn test_layout(&self) -> TestLayout {
+        self.layout
+    }
+
     /// Get the path to the program's release build location.
     pub fn build_release_path(&self) -> &Path {
         &self.build_release
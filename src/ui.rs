@@ -1,8 +1,12 @@
-use crate::command::{RunResult, TestResult, TestStatus};
+use std::io;
+use std::time::Duration;
 
+use crate::command::{Benchmark, Group, RunResult, Stats, TestResult, TestStatus};
+
+mod diff;
 mod step;
 
-use crate::step;
+use crate::{step, stepln};
 
 pub fn print_n_lines(header: &str, data: &[u8], n: usize) {
     let string = String::from_utf8_lossy(data);
@@ -24,6 +28,73 @@ pub fn print_n_lines(header: &str, data: &[u8], n: usize) {
     }
 }
 
+/// Clear the terminal and move the cursor to the top left corner.
+pub fn clear_screen() {
+    eprint!("\x1b[2J\x1b[H");
+}
+
+fn print_stats(label: &str, stats: &Stats) {
+    eprint!(
+        "{:>8} min {}, median {}, mean {}, stddev {}",
+        label,
+        format_time(stats.min),
+        format_time(stats.median),
+        format_time(stats.mean),
+        format_time(stats.stddev),
+    );
+    if stats.is_noisy() {
+        eprint!(
+            " \x1b[1;33mnoisy\x1b[m ({:.0}% of the mean)",
+            stats.spread() * 100.0
+        );
+    }
+    eprintln!();
+}
+
+/// Print the timings of a benchmark. Timings which vary too much to be
+/// trusted are flagged.
+pub fn print_bench_result(case: &str, bench: &Benchmark) {
+    stepln!("BENCH", "{}\x1b[K", case);
+    print_stats("wall", &bench.wall);
+    print_stats("cpu", &bench.cpu);
+}
+
+/// Format a duration with three significant digits.
+pub fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    let millis = time.subsec_millis();
+    let micros = time.subsec_micros() % 1000;
+    if seconds >= 100 {
+        format!("{} s", seconds)
+    } else if seconds >= 10 {
+        format!("{}.{} s", seconds, millis / 100)
+    } else if seconds >= 1 {
+        format!("{}.{:02} s", seconds, millis / 10)
+    } else if millis >= 100 {
+        format!("{} ms", millis)
+    } else if millis >= 10 {
+        format!("{}.{} ms", millis, micros / 100)
+    } else if millis >= 1 {
+        format!("{}.{:02} ms", millis, micros / 10)
+    } else {
+        format!("0.{:03} ms", micros)
+    }
+}
+
+/// Format an amount of memory in bytes.
+pub fn format_memory(bytes: u64) -> String {
+    let mib = bytes as f64 / (1024.0 * 1024.0);
+    if bytes < 1024 * 1024 {
+        format!("{} KiB", bytes / 1024)
+    } else if mib < 10.0 {
+        format!("{:.2} MiB", mib)
+    } else if mib < 100.0 {
+        format!("{:.1} MiB", mib)
+    } else {
+        format!("{:.0} MiB", mib)
+    }
+}
+
 pub fn print_run_result(result: &RunResult) {
     if !result.is_success() {
         eprintln!("--- process completed with {} ---", result);
@@ -34,41 +105,130 @@ pub fn print_test_case(case: &str) {
     step!("TEST", "{}: ", case);
 }
 
-pub fn print_test_result(result: &TestResult) {
+/// Ask the user a yes-or-no question on the terminal. Anything but a yes is
+/// taken as a no.
+pub fn confirm(question: &str) -> bool {
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+pub fn print_skipped() {
+    eprintln!("\x1b[1;90mskipped\x1b[m");
+}
+
+/// Print the result of a test. If the output was wrong, a diff against the
+/// expected output is shown, with the two side by side if `side_by_side` is
+/// set.
+pub fn print_test_result(result: &TestResult, side_by_side: bool) {
     match result.status {
         TestStatus::Pass => eprint!("\x1b[1;32mpass\x1b[m"),
         TestStatus::Wrong => eprint!("\x1b[1;31mwrong\x1b[m"),
         TestStatus::Crash(_) => eprint!("\x1b[1;31mcrash\x1b[m"),
         TestStatus::Timeout => eprint!("\x1b[1;33mtimeout\x1b[m"),
+        TestStatus::MemoryLimit => eprint!("\x1b[1;33mmemory limit\x1b[m"),
+        TestStatus::OutputLimit => eprint!("\x1b[1;33moutput limit\x1b[m"),
+        TestStatus::PresentationError => eprint!("\x1b[1;31mpresentation\x1b[m"),
+        TestStatus::CheckerFail(_) => eprint!("\x1b[1;35mchecker failed\x1b[m"),
+        TestStatus::WrongExitCode { .. } => eprint!("\x1b[1;31mwrong exit code\x1b[m"),
+        TestStatus::WrongStderr => eprint!("\x1b[1;31mwrong stderr\x1b[m"),
+        TestStatus::ForbiddenSyscall => eprint!("\x1b[1;31mforbidden syscall\x1b[m"),
     }
     if result.timeout && result.status != TestStatus::Timeout {
         eprint!("-\x1b[1;33mtimeout\x1b[m");
     }
     eprint!(" ");
 
-    let seconds = result.time.as_secs();
-    let millis = result.time.subsec_millis();
-    let micros = result.time.subsec_micros() % 1000;
-    if seconds >= 100 {
-        eprintln!("{} s", seconds);
-    } else if seconds >= 10 {
-        eprintln!("{}.{} s", seconds, millis / 100);
-    } else if seconds >= 1 {
-        eprintln!("{}.{:02} s", seconds, millis / 10);
-    } else if millis >= 100 {
-        eprintln!("{} ms", millis);
-    } else if millis >= 10 {
-        eprintln!("{}.{} ms", millis, micros / 100);
-    } else if millis >= 1 {
-        eprintln!("{}.{:02} ms", millis, micros / 10);
-    } else {
-        eprintln!("0.{:03} ms", micros);
+    eprint!(
+        "{} (cpu {})",
+        format_time(result.time),
+        format_time(result.cpu_time())
+    );
+    if result.memory > 0 {
+        eprint!(" {}", format_memory(result.memory));
+    }
+    if let (true, Some(name)) = (result.status == TestStatus::Pass, &result.output_name) {
+        eprint!(" (matched {})", name);
     }
+    eprintln!();
 
     if !result.passed() {
-        print_n_lines("captured stderr", &result.stderr, 12);
+        if let Some(description) = &result.description {
+            eprintln!("--- case: {} ---", description);
+        }
+        if result.status != TestStatus::WrongStderr {
+            print_n_lines("captured stderr", &result.stderr, 12);
+        }
+        if let Some(message) = &result.message {
+            print_n_lines("checker message", message.as_bytes(), 12);
+        }
+        if let Some(mismatch) = &result.mismatch {
+            if result.status == TestStatus::WrongStderr {
+                eprintln!("--- stderr differs from the expected stderr ---");
+            } else if let Some(name) = &result.output_name {
+                eprintln!("--- closest expected output is {} ---", name);
+            }
+            diff::print_diff(&mismatch.expected, &mismatch.actual, side_by_side);
+        }
+        if let Some(path) = &result.actual_path {
+            eprintln!("--- actual output saved to {} ---", path.display());
+        }
+        if let Some(transcript) = &result.transcript {
+            eprintln!("--- transcript saved to {} ---", transcript.display());
+        }
+    }
+    match &result.status {
+        TestStatus::Crash(run_result) | TestStatus::CheckerFail(run_result) => {
+            print_run_result(run_result)
+        }
+        TestStatus::WrongExitCode { expected, actual } => {
+            eprintln!(
+                "--- expected exit code {}, process completed with {} ---",
+                expected, actual,
+            );
+        }
+        _ => {}
+    }
+}
+
+/// Print the results of the test groups, and the total score if the groups
+/// have points. `results` has whether each case passed, or `None` if it was
+/// skipped.
+pub fn print_group_results(groups: &[Group], cases: &[String], results: &[Option<bool>]) {
+    let mut score = 0.0;
+    let mut total = 0.0;
+    for group in groups {
+        let passed = group
+            .cases
+            .iter()
+            .filter(|case| {
+                let i = cases.iter().position(|c| c == *case);
+                i.is_some_and(|i| results[i] == Some(true))
+            })
+            .count();
+        step!("GROUP", "{}: ", group.name);
+        if passed == group.cases.len() {
+            eprint!("\x1b[1;32mpass\x1b[m");
+            score += group.points;
+        } else {
+            eprint!("\x1b[1;31mfail\x1b[m");
+        }
+        eprint!(" {}/{} cases", passed, group.cases.len());
+        if group.points > 0.0 {
+            let points = if passed == group.cases.len() {
+                group.points
+            } else {
+                0.0
+            };
+            eprint!(", {}/{} points", points, group.points);
+        }
+        eprintln!();
+        total += group.points;
     }
-    if let TestStatus::Crash(run_result) = &result.status {
-        print_run_result(run_result);
+    if total > 0.0 {
+        stepln!("SCORE", "{}/{}", score, total);
     }
 }
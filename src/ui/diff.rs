@@ -0,0 +1,229 @@
+use std::str;
+
+/// Number of lines shown before and after the first difference.
+const CONTEXT: usize = 2;
+/// Number of bytes per row in the hex view.
+const HEX_ROW: usize = 16;
+
+/// Get the width of the terminal on stderr, or a default if it is unknown.
+fn terminal_width() -> usize {
+    // SAFETY: winsize is a plain C struct, for which all zeroes is valid, and
+    // the pointer is valid for the duration of the call.
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut ws) };
+    if ret == 0 && ws.ws_col > 0 {
+        usize::from(ws.ws_col)
+    } else {
+        80
+    }
+}
+
+/// Get the whitespace-separated tokens of the text, with the line and column
+/// at which each starts.
+fn tokens(text: &str) -> impl Iterator<Item = (usize, usize, &str)> {
+    text.lines().enumerate().flat_map(|(line, s)| {
+        s.split(|c: char| c.is_ascii_whitespace())
+            .filter(|t| !t.is_empty())
+            .map(move |t| {
+                let byte = t.as_ptr() as usize - s.as_ptr() as usize;
+                (line, s[..byte].chars().count(), t)
+            })
+    })
+}
+
+/// Find the first difference between the outputs. Returns the line and column
+/// in the actual output (or the expected output, if the actual output ended),
+/// and a description of the difference.
+fn first_difference(expected: &str, actual: &str) -> Option<(usize, usize, String)> {
+    let mut exp = tokens(expected);
+    let mut act = tokens(actual);
+    loop {
+        match (exp.next(), act.next()) {
+            (Some((_, _, e)), Some((line, col, a))) if e != a => {
+                let what = format!("expected {:?}, found {:?}", clip(e, 0, 40), clip(a, 0, 40));
+                return Some((line, col, what));
+            }
+            (Some((line, col, e)), None) => {
+                let what = format!("expected {:?}, found end of output", clip(e, 0, 40));
+                return Some((line, col, what));
+            }
+            (None, Some((line, col, a))) => {
+                let what = format!("expected end of output, found {:?}", clip(a, 0, 40));
+                return Some((line, col, what));
+            }
+            (None, None) => break,
+            _ => {}
+        }
+    }
+
+    // The tokens are the same, so the difference is in the whitespace
+    let offset = expected
+        .char_indices()
+        .zip(actual.chars())
+        .find(|((_, e), a)| e != a)
+        .map_or_else(|| expected.len().min(actual.len()), |((i, _), _)| i);
+    if offset == expected.len() && offset == actual.len() {
+        return None;
+    }
+    let before = &actual[..offset.min(actual.len())];
+    let line = before.matches('\n').count();
+    let col = before.rsplit('\n').next().unwrap_or("").chars().count();
+    Some((line, col, "whitespace differs".to_string()))
+}
+
+/// Shorten the line to at most `width` characters, keeping the part around
+/// the column visible.
+fn clip(line: &str, col: usize, width: usize) -> String {
+    // Leave room for an ellipsis on both sides of at least one character
+    let width = width.max(3);
+    let len = line.chars().count();
+    if len <= width {
+        return line.to_string();
+    }
+    let start = col.saturating_sub(width / 2).min(len.saturating_sub(width));
+    let mut s: String = line.chars().skip(start).take(width).collect();
+    if start > 0 {
+        if let Some((second, _)) = s.char_indices().nth(1) {
+            s.replace_range(..second, "…");
+        }
+    }
+    if start + width < len {
+        if let Some((last, _)) = s.char_indices().last() {
+            s.replace_range(last.., "…");
+        }
+    }
+    s
+}
+
+/// Print a diff of the expected and actual output of a test, around their
+/// first difference.
+pub fn print_diff(expected: &[u8], actual: &[u8], side_by_side: bool) {
+    let (expected, actual) = match (str::from_utf8(expected), str::from_utf8(actual)) {
+        (Ok(e), Ok(a)) => (e, a),
+        _ => return print_hex_diff(expected, actual),
+    };
+    let (line, col, what) = match first_difference(expected, actual) {
+        Some(d) => d,
+        None => return,
+    };
+    eprintln!(
+        "--- first difference at line {}, column {}: {} ---",
+        line + 1,
+        col + 1,
+        what,
+    );
+
+    let exp_lines: Vec<_> = expected.lines().collect();
+    let act_lines: Vec<_> = actual.lines().collect();
+    let total = exp_lines.len().max(act_lines.len());
+    let first = line.saturating_sub(CONTEXT);
+    let last = (line + CONTEXT + 1).min(total);
+    let num_width = last.to_string().len();
+    let width = terminal_width();
+
+    for i in first..last {
+        let exp = exp_lines.get(i).copied();
+        let act = act_lines.get(i).copied();
+        if side_by_side {
+            // Two columns, with a marker between them where they differ
+            let col_width = width.saturating_sub(num_width + 4) / 2;
+            let marker = if exp == act { ' ' } else { '|' };
+            let exp = clip(exp.unwrap_or(""), col, col_width);
+            let act = clip(act.unwrap_or(""), col, col_width);
+            let pad = col_width.saturating_sub(exp.chars().count());
+            eprintln!(
+                "{:>w$} {}{} {} {}",
+                i + 1,
+                exp,
+                " ".repeat(pad),
+                marker,
+                act,
+                w = num_width,
+            );
+        } else if exp == act {
+            let text = clip(exp.unwrap_or(""), col, width.saturating_sub(num_width + 3));
+            eprintln!("  {:>w$} {}", i + 1, text, w = num_width);
+        } else {
+            let text_width = width.saturating_sub(num_width + 3);
+            if let Some(exp) = exp {
+                let text = clip(exp, col, text_width);
+                eprintln!("\x1b[32m- {:>w$} {}\x1b[m", i + 1, text, w = num_width);
+            }
+            if let Some(act) = act {
+                let text = clip(act, col, text_width);
+                eprintln!("\x1b[31m+ {:>w$} {}\x1b[m", i + 1, text, w = num_width);
+            }
+        }
+    }
+    if last < total {
+        eprintln!("... {} more lines", total - last);
+    }
+}
+
+/// Print a hex dump of the expected and actual output around their first
+/// difference, for output that is not valid UTF-8.
+fn print_hex_diff(expected: &[u8], actual: &[u8]) {
+    let offset = expected
+        .iter()
+        .zip(actual)
+        .position(|(e, a)| e != a)
+        .unwrap_or_else(|| expected.len().min(actual.len()));
+    eprintln!(
+        "--- first difference at byte {} (output is not UTF-8) ---",
+        offset,
+    );
+
+    fn row(data: &[u8], start: usize) -> Option<&[u8]> {
+        data.get(start..).map(|d| &d[..d.len().min(HEX_ROW)])
+    }
+    fn hex(bytes: &[u8]) -> String {
+        bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    let first_row = (offset / HEX_ROW).saturating_sub(1) * HEX_ROW;
+    for start in (first_row..).step_by(HEX_ROW).take(1 + 2 * CONTEXT) {
+        let exp = row(expected, start).filter(|r| !r.is_empty());
+        let act = row(actual, start).filter(|r| !r.is_empty());
+        match (exp, act) {
+            (None, None) => break,
+            (Some(e), Some(a)) if e == a => eprintln!("  {:08x}  {}", start, hex(e)),
+            _ => {
+                if let Some(e) = exp {
+                    eprintln!("\x1b[32m- {:08x}  {}\x1b[m", start, hex(e));
+                }
+                if let Some(a) = act {
+                    eprintln!("\x1b[31m+ {:08x}  {}\x1b[m", start, hex(a));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_keeps_the_column_visible() {
+        assert_eq!(clip("abcdefghij", 5, 5), "…efg…");
+        assert_eq!(clip("abcdefghij", 0, 5), "abcd…");
+        assert_eq!(clip("abcdefghij", 9, 5), "…ghij");
+        assert_eq!(clip("abc", 1, 5), "abc");
+    }
+
+    #[test]
+    fn clip_narrow_widths() {
+        for width in 0..=2 {
+            assert_eq!(clip("a", 0, width), "a");
+            assert_eq!(clip("ab", 0, width), "ab");
+            assert_eq!(clip("ab", 1, width), "ab");
+            assert_eq!(clip("abcdef", 0, width), "ab…");
+            assert_eq!(clip("abcdef", 3, width), "…d…");
+            assert_eq!(clip("abcdef", 5, width), "…ef");
+        }
+    }
+}